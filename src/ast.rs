@@ -1,4 +1,7 @@
-use std::{fmt, ops::{Deref, DerefMut}};
+use std::{fmt, ops::{Deref, DerefMut}, rc::Rc};
+
+use crate::bigint::BigInt;
+use crate::source::FilePosition;
 
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -7,6 +10,7 @@ pub enum Operator {
     Add,
     Mul,
     Div,
+    Mod,
     NotEqual,
     Equal,
     Greater,
@@ -27,6 +31,7 @@ impl fmt::Display for Operator {
             Add => "+",
             Mul => "*",
             Div => "/",
+            Mod => "%",
             NotEqual => "!=",
             Equal => "=",
             Greater => ">",
@@ -48,6 +53,7 @@ impl Operator {
             | Add
             | Mul
             | Div
+            | Mod
             | NotEqual
             | Equal
             | Greater
@@ -79,8 +85,40 @@ impl Operator {
 }
 
 
+/// A function parameter: `name`, `name = default`, or `...name` to
+/// collect the remaining arguments into a list.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Param {
+    pub name: String,
+    pub default: Option<Expr>,
+    pub rest: bool,
+}
+
+impl Param {
+    pub fn new(name: String) -> Param {
+        Param { name, default: None, rest: false }
+    }
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.default, self.rest) {
+            (Some(default), _) => write!(f, "{} = {}", self.name, default),
+            (None, true) => write!(f, "...{}", self.name),
+            (None, false) => write!(f, "{}", self.name),
+        }
+    }
+}
+
+pub fn params_string(params: &[Param]) -> String {
+    params.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Expr {
+    EInt{ value: i64 },
+    EBigInt{ value: BigInt },
     ENumb{ value: f64 },
     EStr{ value: String },
     EBool{ value: bool },
@@ -90,14 +128,29 @@ pub enum Expr {
     EGroup{ expr: Box<Expr> },
     EVar{ name: String },
     EAssign{ name: String, expr: Box<Expr>},
-    ELogicalOp{ op: Operator, left: Box<Expr>, right: Box<Expr> },
-    ECall{ func: Box<Expr>, args: Vec<Expr> },
+    ELogicalOp{ op: Operator, left: Box<Expr>, right: Box<Expr>, pos: FilePosition },
+    /// A call, at the position of its opening parenthesis.
+    ECall{ func: Box<Expr>, args: Vec<Expr>, pos: FilePosition },
+    /// An anonymous function: `fun (a) { ... }`, or `(a) => expr` with a
+    /// body of just a return statement.
+    EFun{ params: Vec<Param>, body: Rc<Stmt>, pos: FilePosition },
+    /// `...expr` in the arguments of a call, passing each item of a list
+    /// as an argument.
+    ESpread{ expr: Box<Expr> },
+    /// `name: expr` in the arguments of a call, passing an argument by the
+    /// name of its parameter.
+    ENamed{ name: String, expr: Box<Expr>, pos: FilePosition },
+    /// A string with interpolated expressions, as the literal parts and
+    /// expressions in order.
+    EInterpolate{ parts: Vec<Expr> },
 }
 
 impl fmt::Display for Expr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         use Expr::*;
         write!(f, "{}", match self {
+            EInt{ value } => format!("{}", value),
+            EBigInt{ value } => format!("{}", value),
             ENumb{ value } => format!("{}", value),
             EStr{ value } => format!("\"{}\"", value),
             EBool{ value } => format!("{}", value),
@@ -120,16 +173,29 @@ impl fmt::Display for Expr {
                 name,
                 expr,
             ),
-            ELogicalOp{ op, left, right} => format!(
+            ELogicalOp{ op, left, right, .. } => format!(
                 "({} {} {})",
                 left,
                 op,
                 right,
             ),
-            ECall{ func, args } => format!(
-                "{}({:?})",
+            ECall{ func, args, .. } => format!(
+                "{}({})",
                 func,
-                args,
+                args.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", "),
+            ),
+            EFun{ params, body, .. } => match &body.kind {
+                StmtKind::SReturn(expr) => format!("({}) => {}", params_string(params), expr),
+                _ => format!("fun({}) {{ ... }}", params_string(params)),
+            },
+            ESpread{ expr } => format!("...{}", expr),
+            ENamed{ name, expr, .. } => format!("{}: {}", name, expr),
+            EInterpolate{ parts } => format!(
+                "\"{}\"",
+                parts.iter().map(|p| match p {
+                    EStr{ value } => value.clone(),
+                    expr => format!("${{{}}}", expr),
+                }).collect::<String>(),
             ),
         })
     }
@@ -137,11 +203,11 @@ impl fmt::Display for Expr {
 
 
 #[derive(Clone, Debug, PartialEq)]
-pub enum Stmt {
+pub enum StmtKind {
     SPrint(Expr),
     SVar(String, Option<Expr>),
     SExpr(Expr),
-    SFun(String, Vec<String>, Box<Stmt>),
+    SFun(String, Vec<Param>, Rc<Stmt>),
     SReturn(Expr),
     SBlock(Vec<Stmt>),
     SIf(Expr, Box<Stmt>, Option<Box<Stmt>>),
@@ -150,6 +216,101 @@ pub enum Stmt {
 }
 
 
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub pos: FilePosition,
+    /// The text of the `///` comments before a `fun` or `var` declaration.
+    pub doc: Option<String>,
+}
+
+impl Stmt {
+    pub fn new(kind: StmtKind, pos: FilePosition) -> Stmt {
+        Stmt { kind, pos, doc: None }
+    }
+
+    pub fn with_doc(self, doc: Option<String>) -> Stmt {
+        Stmt { doc, ..self }
+    }
+
+    pub fn line(&self) -> usize {
+        self.pos.lineno
+    }
+
+    /// The name a `fun` or `var` statement declares.
+    pub fn declared_name(&self) -> Option<&str> {
+        match &self.kind {
+            StmtKind::SFun(name, _, _) | StmtKind::SVar(name, _) => Some(name),
+            _ => None,
+        }
+    }
+
+    fn collect_documented<'a>(&'a self, found: &mut Vec<&'a Stmt>) {
+        use StmtKind::*;
+        if self.doc.is_some() {
+            found.push(self);
+        }
+        match &self.kind {
+            SFun(_, _, body) => body.collect_documented(found),
+            SWhile(_, body) => body.collect_documented(found),
+            SBlock(stmts) => stmts.iter().for_each(|s| s.collect_documented(found)),
+            SIf(_, then, else_) => {
+                then.collect_documented(found);
+                if let Some(else_) = else_ {
+                    else_.collect_documented(found);
+                }
+            },
+            _ => (),
+        }
+    }
+
+    fn fmt_indented(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        use StmtKind::*;
+        let indent = "  ".repeat(depth);
+        for line in self.doc.iter().flat_map(|doc| doc.lines()) {
+            writeln!(f, "{}/// {}", indent, line)?;
+        }
+        match &self.kind {
+            SPrint(expr) => writeln!(f, "{}print {}", indent, expr),
+            SVar(name, Some(expr)) => writeln!(f, "{}var {} = {}", indent, name, expr),
+            SVar(name, None) => writeln!(f, "{}var {}", indent, name),
+            SExpr(expr) => writeln!(f, "{}expr {}", indent, expr),
+            SFun(name, params, body) => {
+                writeln!(f, "{}fun {}({})", indent, name, params_string(params))?;
+                body.fmt_indented(f, depth + 1)
+            },
+            SReturn(expr) => writeln!(f, "{}return {}", indent, expr),
+            SBlock(stmts) => {
+                writeln!(f, "{}block", indent)?;
+                stmts.iter().try_for_each(|s| s.fmt_indented(f, depth + 1))
+            },
+            SIf(cond, then, else_) => {
+                writeln!(f, "{}if {}", indent, cond)?;
+                then.fmt_indented(f, depth + 1)?;
+                if let Some(else_) = else_ {
+                    writeln!(f, "{}else", indent)?;
+                    else_.fmt_indented(f, depth + 1)?;
+                }
+                Ok(())
+            },
+            SWhile(cond, body) => {
+                writeln!(f, "{}while {}", indent, cond)?;
+                body.fmt_indented(f, depth + 1)
+            },
+            SEmpty => writeln!(f, "{}empty", indent),
+        }
+    }
+}
+
+/// One statement per line, with nested statements indented below the
+/// statement that owns them.
+impl fmt::Display for Stmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Interpretable {
     IStmt(Stmt),
@@ -190,6 +351,30 @@ impl AST {
     pub fn new() -> AST {
         AST { top: Interpretables::new() }
     }
+
+    /// The declarations with doc comments, including those nested in
+    /// functions and blocks, in source order.
+    pub fn documented(&self) -> Vec<&Stmt> {
+        let mut found = Vec::new();
+        for interpretable in self.top.iter() {
+            if let Interpretable::IStmt(stmt) = interpretable {
+                stmt.collect_documented(&mut found);
+            }
+        }
+        found
+    }
+}
+
+impl fmt::Display for AST {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for interpretable in self.top.iter() {
+            match interpretable {
+                Interpretable::IStmt(stmt) => write!(f, "{}", stmt)?,
+                Interpretable::IExpr(expr) => writeln!(f, "{}", expr)?,
+            }
+        }
+        Ok(())
+    }
 }
 
 
@@ -216,4 +401,34 @@ mod tests {
         };
         assert_eq!(format!("{}", e), "(-123 * (45.67))");
     }
+
+    #[test]
+    fn test_display_stmt() {
+        use Expr::*;
+        use StmtKind::*;
+        let pos = FilePosition::new(1, 1);
+        let stmt = Stmt::new(SFun(
+            "f".to_string(),
+            vec![Param::new("a".to_string())],
+            Rc::new(Stmt::new(SBlock(vec![
+                Stmt::new(SIf(
+                    EVar { name: "a".to_string() },
+                    Box::new(Stmt::new(SReturn(ENumb { value: 1.0 }), pos)),
+                    None,
+                ), pos),
+                Stmt::new(SPrint(ECall {
+                    func: Box::new(EVar { name: "g".to_string() }),
+                    args: vec![ENumb { value: 2.0 }, ENil],
+                    pos,
+                }), pos),
+            ]), pos)),
+        ), pos);
+        assert_eq!(stmt.to_string(), "\
+fun f(a)
+  block
+    if var a
+      return 1
+    print var g(2, nil)
+");
+    }
 }
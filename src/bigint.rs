@@ -0,0 +1,355 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+use std::str::FromStr;
+
+
+/// An arbitrary-precision integer, for int arithmetic that overflows
+/// 64 bits.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BigInt {
+    negative: bool,
+    // The magnitude in base 2^32, least significant limb first, without
+    // trailing zero limbs, so zero has none.
+    limbs: Vec<u32>,
+}
+
+
+fn trim(mut limbs: Vec<u32>) -> Vec<u32> {
+    while limbs.last() == Some(&0) {
+        limbs.pop();
+    }
+    limbs
+}
+
+
+fn cmp_mag(a: &[u32], b: &[u32]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+
+fn add_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut sum = Vec::with_capacity(long.len() + 1);
+    let mut carry = 0u64;
+    for (idx, limb) in long.iter().enumerate() {
+        let total = *limb as u64 + *short.get(idx).unwrap_or(&0) as u64 + carry;
+        sum.push(total as u32);
+        carry = total >> 32;
+    }
+    sum.push(carry as u32);
+    trim(sum)
+}
+
+
+// `a - b`, where `a` is at least `b`.
+fn sub_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut diff = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (idx, limb) in a.iter().enumerate() {
+        let mut total = *limb as i64 - *b.get(idx).unwrap_or(&0) as i64 - borrow;
+        borrow = 0;
+        if total < 0 {
+            total += 1 << 32;
+            borrow = 1;
+        }
+        diff.push(total as u32);
+    }
+    trim(diff)
+}
+
+
+fn mul_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut product = vec![0u32; a.len() + b.len()];
+    for (i, x) in a.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, y) in b.iter().enumerate() {
+            let total = product[i + j] as u64 + *x as u64 * *y as u64 + carry;
+            product[i + j] = total as u32;
+            carry = total >> 32;
+        }
+        product[i + b.len()] = carry as u32;
+    }
+    trim(product)
+}
+
+
+// `a * m + c`, in place.
+fn mul_small_add(a: &mut Vec<u32>, m: u32, c: u32) {
+    let mut carry = c as u64;
+    for limb in a.iter_mut() {
+        let total = *limb as u64 * m as u64 + carry;
+        *limb = total as u32;
+        carry = total >> 32;
+    }
+    if carry > 0 {
+        a.push(carry as u32);
+    }
+}
+
+
+// `a / d` and `a % d` for a nonzero single-limb `d`.
+fn divmod_small(a: &[u32], d: u32) -> (Vec<u32>, u32) {
+    let mut quotient = vec![0u32; a.len()];
+    let mut rem = 0u64;
+    for (idx, limb) in a.iter().enumerate().rev() {
+        let total = (rem << 32) | *limb as u64;
+        quotient[idx] = (total / d as u64) as u32;
+        rem = total % d as u64;
+    }
+    (trim(quotient), rem as u32)
+}
+
+
+// `a / b` and `a % b` for a nonzero `b`, by binary long division.
+fn divmod_mag(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if b.len() == 1 {
+        let (quotient, rem) = divmod_small(a, b[0]);
+        return (quotient, trim(vec![rem]));
+    }
+    if cmp_mag(a, b) == Ordering::Less {
+        return (Vec::new(), a.to_vec());
+    }
+
+    let mut quotient = vec![0u32; a.len()];
+    let mut rem: Vec<u32> = Vec::new();
+    for bit in (0..a.len() * 32).rev() {
+        mul_small_add(&mut rem, 2, (a[bit / 32] >> (bit % 32)) & 1);
+        if cmp_mag(&rem, b) != Ordering::Less {
+            rem = sub_mag(&rem, b);
+            quotient[bit / 32] |= 1 << (bit % 32);
+        }
+    }
+    (trim(quotient), rem)
+}
+
+
+impl BigInt {
+    fn new(negative: bool, limbs: Vec<u32>) -> BigInt {
+        let limbs = trim(limbs);
+        BigInt {
+            negative: negative && !limbs.is_empty(),
+            limbs,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    /// Parses unsigned `digits` in `radix`, from 2 to 36.
+    pub fn parse_radix(digits: &str, radix: u32) -> Option<BigInt> {
+        if digits.is_empty() {
+            return None;
+        }
+        let mut limbs = Vec::new();
+        for ch in digits.chars() {
+            mul_small_add(&mut limbs, radix, ch.to_digit(radix)?);
+        }
+        Some(BigInt::new(false, limbs))
+    }
+
+    /// The value, if it fits in an `i64`.
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.limbs.len() > 2 {
+            return None;
+        }
+        let magnitude = self.limbs.iter().rev().fold(0u64, |acc, limb| (acc << 32) | *limb as u64);
+        match self.negative {
+            true => 0i64.checked_sub_unsigned(magnitude),
+            false => i64::try_from(magnitude).ok(),
+        }
+    }
+
+    /// The nearest float, or an infinity if it is too large.
+    pub fn to_f64(&self) -> f64 {
+        let magnitude = self.limbs.iter().rev().fold(0.0, |acc, limb| acc * 4294967296.0 + *limb as f64);
+        if self.negative { -magnitude } else { magnitude }
+    }
+
+    /// The integer part of `value`, if it is finite.
+    pub fn from_f64(value: f64) -> Option<BigInt> {
+        if !value.is_finite() {
+            return None;
+        }
+        let value = value.trunc();
+        if value.abs() < 9.2e18 {
+            return Some(BigInt::from(value as i64));
+        }
+        // a float this large is an integer mantissa times a power of two
+        let bits = value.to_bits();
+        let exponent = ((bits >> 52) & 0x7ff) as usize - 1075;
+        let mantissa = (bits & ((1 << 52) - 1)) | (1 << 52);
+        let mut limbs = vec![0u32; exponent / 32];
+        limbs.extend([mantissa as u32, (mantissa >> 32) as u32]);
+        for _ in 0..exponent % 32 {
+            mul_small_add(&mut limbs, 2, 0);
+        }
+        Some(BigInt::new(value < 0.0, limbs))
+    }
+
+    /// The quotient rounded toward zero and the remainder, which has the
+    /// sign of `self`, or `None` when dividing by zero.
+    pub fn div_rem(&self, other: &BigInt) -> Option<(BigInt, BigInt)> {
+        if other.is_zero() {
+            return None;
+        }
+        let (quotient, rem) = divmod_mag(&self.limbs, &other.limbs);
+        Some((
+            BigInt::new(self.negative != other.negative, quotient),
+            BigInt::new(self.negative, rem),
+        ))
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(value: i64) -> BigInt {
+        let magnitude = value.unsigned_abs();
+        BigInt::new(value < 0, vec![magnitude as u32, (magnitude >> 32) as u32])
+    }
+}
+
+impl FromStr for BigInt {
+    type Err = String;
+
+    /// Parses decimal digits with an optional sign.
+    fn from_str(text: &str) -> Result<BigInt, String> {
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        match BigInt::parse_radix(digits, 10) {
+            Some(value) => Ok(BigInt::new(negative, value.limbs)),
+            None => Err(format!("invalid integer: {}", text)),
+        }
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        // nine decimal digits at a time, least significant first
+        let mut chunks = Vec::new();
+        let mut limbs = self.limbs.clone();
+        while !limbs.is_empty() {
+            let (quotient, rem) = divmod_small(&limbs, 1_000_000_000);
+            chunks.push(rem);
+            limbs = quotient;
+        }
+        let mut text = String::from(if self.negative { "-" } else { "" });
+        text.push_str(&chunks.pop().unwrap().to_string());
+        for chunk in chunks.iter().rev() {
+            text.push_str(&format!("{:09}", chunk));
+        }
+        write!(f, "{}", text)
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_mag(&self.limbs, &other.limbs),
+            (true, true) => cmp_mag(&other.limbs, &self.limbs),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::new(!self.negative, self.limbs.clone())
+    }
+}
+
+impl Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::new(self.negative, add_mag(&self.limbs, &other.limbs));
+        }
+        // opposite signs, so the larger magnitude decides the sign
+        match cmp_mag(&self.limbs, &other.limbs) {
+            Ordering::Less => BigInt::new(other.negative, sub_mag(&other.limbs, &self.limbs)),
+            _ => BigInt::new(self.negative, sub_mag(&self.limbs, &other.limbs)),
+        }
+    }
+}
+
+impl Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, other: &BigInt) -> BigInt {
+        self + &-other
+    }
+}
+
+impl Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: &BigInt) -> BigInt {
+        BigInt::new(self.negative != other.negative, mul_mag(&self.limbs, &other.limbs))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn big(text: &str) -> BigInt {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn test_display_and_parse() {
+        for text in ["0", "7", "-42", "4294967296", "-18446744073709551616", "1000000000000000000000000000001"] {
+            assert_eq!(big(text).to_string(), text);
+        }
+        assert_eq!(BigInt::from(i64::MIN).to_string(), "-9223372036854775808");
+        assert_eq!(BigInt::parse_radix("ffffffffffffffffff", 16).unwrap().to_string(), "4722366482869645213695");
+        assert_eq!(big("-0"), BigInt::from(0));
+        assert!("12a".parse::<BigInt>().is_err());
+    }
+
+    #[test]
+    fn test_arithmetic_matches_i128() {
+        let values: [i128; 7] = [0, 1, -1, 4294967295, -4294967296, i64::MAX as i128 + 7, -(i64::MAX as i128) - 5];
+        for a in values {
+            for b in values {
+                let (x, y) = (big(&a.to_string()), big(&b.to_string()));
+                assert_eq!((&x + &y).to_string(), (a + b).to_string());
+                assert_eq!((&x - &y).to_string(), (a - b).to_string());
+                assert_eq!((&x * &y).to_string(), (a * b).to_string());
+                assert_eq!(x.cmp(&y), a.cmp(&b));
+                if b != 0 {
+                    let (q, r) = x.div_rem(&y).unwrap();
+                    assert_eq!((q.to_string(), r.to_string()), ((a / b).to_string(), (a % b).to_string()));
+                }
+            }
+        }
+        assert_eq!(big("5").div_rem(&BigInt::from(0)), None);
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(big("-9223372036854775808").to_i64(), Some(i64::MIN));
+        assert_eq!(big("9223372036854775808").to_i64(), None);
+        assert_eq!(big("-18446744073709551616").to_f64(), -18446744073709551616.0);
+        assert_eq!(BigInt::from_f64(1e20).unwrap().to_string(), "100000000000000000000");
+        assert_eq!(BigInt::from_f64(-2.5).unwrap(), BigInt::from(-2));
+        assert_eq!(BigInt::from_f64(f64::NAN), None);
+    }
+}
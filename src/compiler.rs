@@ -0,0 +1,581 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::ast::{Expr, Interpretable, Interpretables, Operator, Param, Stmt, StmtKind};
+use crate::environment::Environment;
+use crate::evaluator::{call_with, check_callable, match_call, Call, Flow};
+use crate::hooks;
+use crate::source::FilePosition;
+use crate::value::{LoxType, LoxValue};
+
+
+/// How `Interpreter` runs a program once it is parsed.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Backend {
+    /// Walk the AST with `evaluator`.
+    #[default]
+    Tree,
+    /// Compile the AST into closures with `Compiler` and run those.
+    Closures,
+}
+
+impl Backend {
+    pub const NAMES: [&'static str; 2] = ["tree", "closures"];
+
+    pub fn parse(name: &str) -> Result<Backend, String> {
+        match name.trim() {
+            "tree" => Ok(Backend::Tree),
+            "closures" => Ok(Backend::Closures),
+            name => Err(format!(
+                "unknown backend '{}', expected one of {}",
+                name,
+                Backend::NAMES.join(", "),
+            )),
+        }
+    }
+}
+
+
+type Expression = Box<dyn Fn(&Compiler, &Rc<Environment>) -> Result<LoxValue, String>>;
+type Statement = Box<dyn Fn(&Compiler, &Rc<Environment>) -> Result<Flow, String>>;
+type PrepareCall = Box<dyn Fn(&Compiler, &Rc<Environment>) -> Result<Call, String>>;
+type BinaryOp = fn(&LoxValue, &LoxValue) -> Result<LoxValue, String>;
+type UnaryOp = fn(&LoxValue) -> Result<LoxValue, String>;
+
+
+// A compiled function body, shared by every function value made from it.
+struct Function {
+    // the name and default of each parameter
+    params: Vec<(String, Option<Expression>)>,
+    body: Statement,
+}
+
+
+enum Argument {
+    Value(Expression),
+    Spread(Expression),
+    Named(String, FilePosition, Expression),
+}
+
+
+enum Compiled {
+    Stmt(Statement),
+    Expr(Expression),
+}
+
+
+// Compiled function bodies by the address of the body, which the AST and
+// the function values made from it share. Keeping the body keeps the
+// address from being reused.
+type Functions = HashMap<*const Stmt, (Rc<Stmt>, Rc<Function>)>;
+
+
+/// Compiles each expression and statement of an AST once into a closure
+/// that runs it, with the operator it applies picked at compile time, so
+/// running a program doesn't walk the AST. Programs behave as they do
+/// with `evaluator`, hooks included, though hooks see each statement
+/// without the statements nested in it.
+#[derive(Default)]
+pub struct Compiler {
+    functions: RefCell<Functions>,
+}
+
+impl Compiler {
+    pub fn new() -> Compiler {
+        Compiler::default()
+    }
+
+    /// Compiles `interpretables` and runs them in `env`, like
+    /// `evaluator::interpret`.
+    pub fn run(&self, interpretables: &Interpretables, env: &Rc<Environment>) -> Result<Option<LoxValue>, String> {
+        let program: Vec<Compiled> = interpretables.iter()
+            .map(|interpretable| match interpretable {
+                Interpretable::IStmt(stmt) => Compiled::Stmt(self.stmt(stmt)),
+                Interpretable::IExpr(expr) => Compiled::Expr(self.expr(expr)),
+            })
+            .collect();
+
+        for compiled in program.iter() {
+            match compiled {
+                Compiled::Stmt(stmt) => match stmt(self, env)? {
+                    Flow::Next => (),
+                    Flow::Return(value) => return Ok(Some(value)),
+                    Flow::TailCall(tail) => return self.call(tail).map(Some),
+                },
+                Compiled::Expr(expr) => return expr(self, env).map(Some),
+            }
+        }
+        Ok(None)
+    }
+
+    fn call(&self, next: Call) -> Result<LoxValue, String> {
+        call_with(next, |func, values| {
+            let LoxType::VCallable(_, params, body, closure, _) = &**func else {
+                unreachable!("call_with runs natives itself");
+            };
+            let function = self.function(params, body);
+            let env = Environment::new_child(closure);
+            for ((name, default), value) in function.params.iter().zip(values) {
+                let value = match (value, default) {
+                    (Some(value), _) => value,
+                    (None, Some(default)) => default(self, &env)?,
+                    (None, None) => unreachable!("missing arguments are checked before binding"),
+                };
+                env.var(name, Some(value));
+            }
+            (function.body)(self, &env)
+        })
+    }
+
+    // The compiled function with `body`, compiling it the first time.
+    fn function(&self, params: &[Param], body: &Rc<Stmt>) -> Rc<Function> {
+        if let Some((_, function)) = self.functions.borrow().get(&Rc::as_ptr(body)) {
+            return function.clone();
+        }
+        let function = Rc::new(Function {
+            params: params.iter()
+                .map(|p| (p.name.clone(), p.default.as_ref().map(|e| self.expr(e))))
+                .collect(),
+            body: self.stmt(body),
+        });
+        self.functions.borrow_mut().insert(Rc::as_ptr(body), (body.clone(), function.clone()));
+        function
+    }
+
+    fn expr(&self, expr: &Expr) -> Expression {
+        use Expr::*;
+        use LoxType::*;
+        match expr {
+            EInt { value } => constant(VInt(*value)),
+            EBigInt { value } => constant(VBigInt(value.clone())),
+            ENumb { value } => constant(VNumb(*value)),
+            EStr { value } => constant(VStr(value.clone())),
+            EBool { value } => constant(VBool(*value)),
+            ENil => constant(VNil),
+            EBinOp { op, left, right } => {
+                let (left, right) = (self.expr(left), self.expr(right));
+                match binary(op) {
+                    Some(apply) => Box::new(move |c, env| apply(&left(c, env)?, &right(c, env)?)),
+                    None => {
+                        let msg = format!("Unsupported binary operation: {}", op);
+                        Box::new(move |c, env| {
+                            left(c, env)?;
+                            right(c, env)?;
+                            Err(msg.clone())
+                        })
+                    },
+                }
+            },
+            EUnaryOp { op, operand } => {
+                let operand = self.expr(operand);
+                match unary(op) {
+                    Some(apply) => Box::new(move |c, env| apply(&operand(c, env)?)),
+                    None => {
+                        let msg = format!("Unsupported unary operation: {}", op);
+                        Box::new(move |c, env| {
+                            operand(c, env)?;
+                            Err(msg.clone())
+                        })
+                    },
+                }
+            },
+            EGroup { expr } => self.expr(expr),
+            EVar { name } => {
+                let name = name.clone();
+                Box::new(move |_, env| {
+                    let value = env.lookup(&name)?;
+                    hooks::read(&name, &value);
+                    Ok(value)
+                })
+            },
+            EAssign { name, expr } => {
+                let (name, expr) = (name.clone(), self.expr(expr));
+                Box::new(move |c, env| {
+                    let value = env.assign(&name, expr(c, env)?)?;
+                    hooks::write(&name, Some(&value));
+                    Ok(value)
+                })
+            },
+            ELogicalOp { op, left, right, pos } => {
+                // whether the left operand decides the result
+                let decides: fn(&LoxValue) -> bool = match op {
+                    Operator::Or => |left| left._is_truthy(),
+                    Operator::And => |left| !left._is_truthy(),
+                    _ => return failure(format!("Unsupported logical operation: {}", op)),
+                };
+                let (left, right, pos) = (self.expr(left), self.expr(right), *pos);
+                Box::new(move |c, env| {
+                    let left = left(c, env)?;
+                    let decided = decides(&left);
+                    hooks::branch(&pos, decided as usize);
+                    if decided {
+                        return Ok(left);
+                    }
+                    right(c, env)
+                })
+            },
+            ECall { func, args, pos } => {
+                let prepare = self.prepare_call(func, args, pos);
+                Box::new(move |c, env| c.call(prepare(c, env)?))
+            },
+            EFun { params, body, pos } => {
+                self.function(params, body);
+                let (params, body, pos) = (params.clone(), body.clone(), *pos);
+                Box::new(move |_, env| Ok(LoxValue::new(VCallable(
+                    String::from("lambda"),
+                    params.clone(),
+                    body.clone(),
+                    Environment::new_child(env),
+                    pos,
+                ))))
+            },
+            ESpread { .. } => failure("Spread is only allowed in call arguments".to_string()),
+            ENamed { .. } => failure("Named arguments are only allowed in calls".to_string()),
+            EInterpolate { parts } => {
+                let parts: Vec<Expression> = parts.iter().map(|p| self.expr(p)).collect();
+                Box::new(move |c, env| {
+                    let mut value = String::new();
+                    for part in parts.iter() {
+                        value.push_str(&part(c, env)?.value_string());
+                    }
+                    Ok(LoxValue::new(VStr(value)))
+                })
+            },
+        }
+    }
+
+    fn prepare_call(&self, func: &Expr, args: &[Expr], pos: &FilePosition) -> PrepareCall {
+        let func = self.expr(func);
+        let args: Vec<Argument> = args.iter()
+            .map(|arg| match arg {
+                Expr::ESpread { expr } => Argument::Spread(self.expr(expr)),
+                Expr::ENamed { name, expr, pos } => Argument::Named(name.clone(), *pos, self.expr(expr)),
+                arg => Argument::Value(self.expr(arg)),
+            })
+            .collect();
+        let pos = *pos;
+        Box::new(move |c, env| {
+            let func = func(c, env)?;
+            check_callable(&func)?;
+
+            let mut positional = Vec::new();
+            let mut named = Vec::new();
+            for arg in args.iter() {
+                match arg {
+                    Argument::Value(expr) => positional.push(expr(c, env)?),
+                    Argument::Spread(expr) => match &*expr(c, env)? {
+                        LoxType::VList(items) => positional.extend(items.iter().cloned()),
+                        typ => return Err(format!("Cannot spread {} into arguments", typ)),
+                    },
+                    Argument::Named(name, pos, expr) => named.push((name.as_str(), pos, expr(c, env)?)),
+                }
+            }
+            match_call(func, positional, named, &pos)
+        })
+    }
+
+    // Compiles `stmt`, notifying hooks before and after it runs.
+    fn stmt(&self, stmt: &Stmt) -> Statement {
+        let run = self.stmt_kind(stmt);
+        let stmt = shallow(stmt);
+        Box::new(move |c, env| {
+            hooks::before_stmt(&stmt, env)?;
+            let result = run(c, env);
+            hooks::after_stmt(&stmt);
+            result
+        })
+    }
+
+    fn stmt_kind(&self, stmt: &Stmt) -> Statement {
+        use StmtKind::*;
+        let pos = stmt.pos;
+        match &stmt.kind {
+            SPrint(expr) => {
+                let expr = self.expr(expr);
+                Box::new(move |c, env| {
+                    let text = expr(c, env)?.value_string();
+                    if !hooks::print(&text) {
+                        println!("{}", text);
+                    }
+                    Ok(Flow::Next)
+                })
+            },
+            SExpr(expr) => {
+                let expr = self.expr(expr);
+                Box::new(move |c, env| {
+                    expr(c, env)?;
+                    Ok(Flow::Next)
+                })
+            },
+            SVar(name, Some(expr)) => {
+                let (name, expr) = (name.clone(), self.expr(expr));
+                Box::new(move |c, env| {
+                    let value = expr(c, env)?;
+                    hooks::write(&name, Some(&value));
+                    env.var(&name, Some(value));
+                    Ok(Flow::Next)
+                })
+            },
+            SVar(name, None) => {
+                let name = name.clone();
+                Box::new(move |_, env| {
+                    hooks::write(&name, None);
+                    env.var(&name, None);
+                    Ok(Flow::Next)
+                })
+            },
+            SBlock(stmts) => {
+                let stmts: Vec<Statement> = stmts.iter().map(|s| self.stmt(s)).collect();
+                Box::new(move |c, env| {
+                    let env = Environment::new_child(env);
+                    for stmt in stmts.iter() {
+                        match stmt(c, &env)? {
+                            Flow::Next => (),
+                            flow => return Ok(flow),
+                        }
+                    }
+                    Ok(Flow::Next)
+                })
+            },
+            SIf(cond, then, else_) => {
+                let (cond, then) = (self.expr(cond), self.stmt(then));
+                let else_ = else_.as_ref().map(|e| self.stmt(e));
+                Box::new(move |c, env| {
+                    if cond(c, env)?._is_truthy() {
+                        hooks::branch(&pos, 0);
+                        return then(c, env);
+                    }
+
+                    hooks::branch(&pos, 1);
+                    match &else_ {
+                        Some(else_) => else_(c, env),
+                        None => Ok(Flow::Next),
+                    }
+                })
+            },
+            SWhile(cond, body) => {
+                let (cond, body) = (self.expr(cond), self.stmt(body));
+                Box::new(move |c, env| {
+                    let mut entered = false;
+                    while cond(c, env)?._is_truthy() {
+                        if !entered {
+                            hooks::branch(&pos, 0);
+                            entered = true;
+                        }
+                        match body(c, env)? {
+                            Flow::Next => (),
+                            flow => return Ok(flow),
+                        }
+                    }
+                    if !entered {
+                        hooks::branch(&pos, 1);
+                    }
+                    Ok(Flow::Next)
+                })
+            },
+            SFun(name, params, body) => {
+                self.function(params, body);
+                let (name, params, body) = (name.clone(), params.clone(), body.clone());
+                Box::new(move |_, env| {
+                    let func = LoxValue::new(LoxType::VCallable(
+                        name.clone(),
+                        params.clone(),
+                        body.clone(),
+                        Environment::new_child(env),
+                        pos,
+                    ));
+                    hooks::write(&name, Some(&func));
+                    env.var(&name, Some(func));
+                    Ok(Flow::Next)
+                })
+            },
+            SReturn(Expr::ECall { func, args, pos }) => {
+                let prepare = self.prepare_call(func, args, pos);
+                Box::new(move |c, env| Ok(Flow::TailCall(prepare(c, env)?)))
+            },
+            SReturn(expr) => {
+                let expr = self.expr(expr);
+                Box::new(move |c, env| Ok(Flow::Return(expr(c, env)?)))
+            },
+            SEmpty => Box::new(|_, _| Ok(Flow::Next)),
+        }
+    }
+}
+
+
+// `stmt` without the statements nested in it, for hooks, so compiling a
+// statement doesn't copy the whole subtree under it.
+fn shallow(stmt: &Stmt) -> Stmt {
+    use StmtKind::*;
+    let empty = || Box::new(Stmt::new(SEmpty, stmt.pos));
+    let kind = match &stmt.kind {
+        SBlock(_) => SBlock(Vec::new()),
+        SIf(cond, _, else_) => SIf(cond.clone(), empty(), else_.as_ref().map(|_| empty())),
+        SWhile(cond, _) => SWhile(cond.clone(), empty()),
+        kind => kind.clone(),
+    };
+    Stmt { kind, pos: stmt.pos, doc: stmt.doc.clone() }
+}
+
+
+fn constant(value: LoxType) -> Expression {
+    let value = LoxValue::new(value);
+    Box::new(move |_, _| Ok(value.clone()))
+}
+
+
+fn failure(msg: String) -> Expression {
+    Box::new(move |_, _| Err(msg.clone()))
+}
+
+
+fn binary(op: &Operator) -> Option<BinaryOp> {
+    use Operator::*;
+    Some(match op {
+        Sub => LoxValue::sub,
+        Add => LoxValue::add,
+        Mul => LoxValue::mul,
+        Div => LoxValue::div,
+        Mod => LoxValue::rem,
+        NotEqual => LoxValue::neq,
+        Equal => LoxValue::eq,
+        Greater => LoxValue::gt,
+        GreaterEqual => LoxValue::ge,
+        Less => LoxValue::lt,
+        LessEqual => LoxValue::le,
+        _ => return None,
+    })
+}
+
+
+fn unary(op: &Operator) -> Option<UnaryOp> {
+    use Operator::*;
+    Some(match op {
+        Not => LoxValue::not,
+        Negate => LoxValue::negate,
+        _ => return None,
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::golden;
+    use crate::hooks::Hook;
+    use crate::interpreter::Interpreter;
+    use crate::optimizer::Passes;
+    use crate::parser::Dialect;
+    use crate::source::Source;
+    use pretty_assertions::assert_eq;
+
+    struct Events(Rc<RefCell<Vec<String>>>);
+
+    impl Hook for Events {
+        fn before_stmt(&mut self, stmt: &Stmt, _env: &Rc<Environment>) -> Result<(), String> {
+            self.0.borrow_mut().push(format!("line {}", stmt.line()));
+            Ok(())
+        }
+
+        fn enter_call(&mut self, func: &LoxValue, args: &[LoxValue]) -> Result<(), String> {
+            let args: Vec<String> = args.iter().map(|a| a.value_string()).collect();
+            self.0.borrow_mut().push(format!("call {} {:?}", func.value_string(), args));
+            Ok(())
+        }
+
+        fn exit_call(&mut self, func: &LoxValue, result: Option<&LoxValue>) {
+            self.0.borrow_mut().push(format!("exit {} {:?}", func.value_string(), result.map(|r| r.value_string())));
+        }
+
+        fn branch(&mut self, pos: &FilePosition, branch: usize) {
+            self.0.borrow_mut().push(format!("branch {} {}", pos.lineno, branch));
+        }
+
+        fn read(&mut self, name: &str, value: &LoxValue) {
+            self.0.borrow_mut().push(format!("read {} {}", name, value.value_string()));
+        }
+
+        fn write(&mut self, name: &str, value: Option<&LoxValue>) {
+            self.0.borrow_mut().push(format!("write {} {:?}", name, value.map(|v| v.value_string())));
+        }
+
+        fn print(&mut self, text: &str) -> bool {
+            self.0.borrow_mut().push(format!("print {}", text));
+            true
+        }
+    }
+
+    fn events(code: &str, backend: Backend) -> Vec<String> {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let installed = hooks::clear();
+        hooks::install(Box::new(Events(events.clone())));
+        let result = Interpreter::new()
+            .with_backend(backend)
+            .interpret(&mut Source::from_string(code.to_string()));
+        hooks::clear();
+        installed.into_iter().for_each(hooks::install);
+
+        let mut events = events.take();
+        events.push(format!("result {:?}", result));
+        events
+    }
+
+    #[test]
+    fn test_same_behavior() {
+        let code = "\
+fun count(n, total = 0) {
+    if n == 0 { return total; }
+    return count(n - 1, total: (total + n));
+}
+var add = (a, ...rest) => \"${a}${rest}\";
+var pass = (...xs) => add(0, ...xs);
+print count(3); // expect: 6
+print pass(1, 2); // expect: 0[1, 2]
+print (nil or 2) and !false; // expect: true
+var i = 0;
+while i < 2 { i = i + 1; }
+print i; // expect: 2
+print count(1, x: 1); // expect runtime error: Function count has no parameter named 'x'
+";
+        let mut src = Source::from_string(code.to_string());
+        let failures = golden::check(&mut src, Dialect::Bwl, Passes::default(), Backend::Closures);
+        assert_eq!(failures, Vec::<String>::new());
+        assert_eq!(events(code, Backend::Closures), events(code, Backend::Tree));
+    }
+
+    #[test]
+    fn test_functions_compiled_once() {
+        let compiler = Compiler::new();
+        let src = Source::from_string("\
+var fs = 0;
+var i = 0;
+while i < 3 {
+    fun f() { return () => i; }
+    fs = f;
+    i = i + 1;
+}
+fs()();
+".to_string());
+        let ast = crate::parser::parse(&crate::tokenizer::tokenize(&src).unwrap()).unwrap();
+        compiler.run(&ast.top, &Environment::new()).unwrap();
+        assert_eq!(compiler.functions.borrow().len(), 2);
+    }
+
+    #[test]
+    fn test_shallow() {
+        let src = Source::from_string("while x { if y { print 1; } }".to_string());
+        let ast = crate::parser::parse(&crate::tokenizer::tokenize(&src).unwrap()).unwrap();
+        let Interpretable::IStmt(stmt) = &ast.top[0] else { panic!("expected a statement") };
+        assert_eq!(shallow(stmt).to_string(), "while var x\n  empty\n");
+    }
+
+    #[test]
+    fn test_parse_backend() {
+        assert_eq!(Backend::parse("closures"), Ok(Backend::Closures));
+        assert_eq!(
+            Backend::parse("vm"),
+            Err("unknown backend 'vm', expected one of tree, closures".to_string()),
+        );
+    }
+}
@@ -0,0 +1,381 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use crate::ast::{Expr, Interpretable, Operator, Stmt, StmtKind, AST};
+use crate::environment::Environment;
+use crate::hooks::Hook;
+use crate::source::{FilePosition, Source};
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BranchKind {
+    If,
+    While,
+    And,
+    Or,
+}
+
+impl BranchKind {
+    fn labels(&self) -> [&'static str; 2] {
+        use BranchKind::*;
+        match self {
+            If => ["then", "else"],
+            While => ["entered", "skipped"],
+            And | Or => ["evaluated", "short-circuited"],
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        use BranchKind::*;
+        match self {
+            If => "if",
+            While => "while",
+            And => "and",
+            Or => "or",
+        }
+    }
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Branch {
+    pub kind: BranchKind,
+    pub taken: [usize; 2],
+}
+
+
+// Branch points are keyed by (line, column), and statements by that and
+// whether they are blocks, since a desugared `for` puts a block and its
+// loop at one position.
+#[derive(Default)]
+pub struct FileCoverage {
+    pub name: String,
+    content: Rc<String>,
+    pub stmts: BTreeMap<(usize, usize, bool), usize>,
+    pub branches: BTreeMap<(usize, usize), Branch>,
+}
+
+impl FileCoverage {
+    /// Execution count per line: the highest count of any statement
+    /// starting on it.
+    pub fn lines(&self) -> BTreeMap<usize, usize> {
+        let mut lines = BTreeMap::new();
+        for ((line, ..), count) in self.stmts.iter() {
+            let entry = lines.entry(*line).or_insert(0);
+            *entry = (*entry).max(*count);
+        }
+        lines
+    }
+
+    fn add_stmt(&mut self, stmt: &Stmt) {
+        use StmtKind::*;
+        if stmt.kind != SEmpty {
+            self.stmts.entry(stmt_key(stmt)).or_insert(0);
+        }
+        match &stmt.kind {
+            SPrint(expr) | SExpr(expr) | SReturn(expr) | SVar(_, Some(expr)) => self.add_expr(expr),
+            SVar(_, None) | SEmpty => (),
+            SFun(_, params, body) => {
+                params.iter().filter_map(|p| p.default.as_ref()).for_each(|e| self.add_expr(e));
+                self.add_stmt(body);
+            },
+            SBlock(stmts) => stmts.iter().for_each(|s| self.add_stmt(s)),
+            SIf(cond, then, else_) => {
+                self.add_branch(&stmt.pos, BranchKind::If);
+                self.add_expr(cond);
+                self.add_stmt(then);
+                if let Some(else_) = else_ {
+                    self.add_stmt(else_);
+                }
+            },
+            SWhile(cond, body) => {
+                self.add_branch(&stmt.pos, BranchKind::While);
+                self.add_expr(cond);
+                self.add_stmt(body);
+            },
+        }
+    }
+
+    fn add_expr(&mut self, expr: &Expr) {
+        use Expr::*;
+        match expr {
+            EInt { .. } | EBigInt { .. } | ENumb { .. } | EStr { .. } | EBool { .. } | ENil | EVar { .. } => (),
+            EBinOp { left, right, .. } => {
+                self.add_expr(left);
+                self.add_expr(right);
+            },
+            EUnaryOp { operand: expr, .. }
+            | EGroup { expr }
+            | EAssign { expr, .. }
+            | ESpread { expr }
+            | ENamed { expr, .. } => {
+                self.add_expr(expr)
+            },
+            ELogicalOp { op, left, right, pos } => {
+                let kind = match op {
+                    Operator::And => BranchKind::And,
+                    _ => BranchKind::Or,
+                };
+                self.add_branch(pos, kind);
+                self.add_expr(left);
+                self.add_expr(right);
+            },
+            ECall { func, args, .. } => {
+                self.add_expr(func);
+                args.iter().for_each(|a| self.add_expr(a));
+            },
+            EFun { params, body, .. } => {
+                params.iter().filter_map(|p| p.default.as_ref()).for_each(|e| self.add_expr(e));
+                self.add_stmt(body);
+            },
+            EInterpolate { parts } => parts.iter().for_each(|p| self.add_expr(p)),
+        }
+    }
+
+    fn add_branch(&mut self, pos: &FilePosition, kind: BranchKind) {
+        self.branches.entry(key(pos)).or_insert(Branch { kind, taken: [0, 0] });
+    }
+
+    /// The coverage in lcov tracefile format.
+    pub fn lcov(&self) -> String {
+        let mut out = format!("TN:\nSF:{}\n", self.name);
+
+        let mut block = 0;
+        let mut last_line = 0;
+        for ((line, _), branch) in self.branches.iter() {
+            block = if *line == last_line { block + 1 } else { 0 };
+            last_line = *line;
+            for (idx, taken) in branch.taken.iter().enumerate() {
+                out.push_str(&format!("BRDA:{},{},{},{}\n", line, block, idx, taken));
+            }
+        }
+        let found = self.branches.len() * 2;
+        let hit: usize = self.branches.values()
+            .map(|b| b.taken.iter().filter(|t| **t > 0).count())
+            .sum();
+        out.push_str(&format!("BRF:{}\nBRH:{}\n", found, hit));
+
+        let lines = self.lines();
+        for (line, count) in lines.iter() {
+            out.push_str(&format!("DA:{},{}\n", line, count));
+        }
+        let hit = lines.values().filter(|c| **c > 0).count();
+        out.push_str(&format!("LF:{}\nLH:{}\nend_of_record\n", lines.len(), hit));
+        out
+    }
+
+    /// The source with execution counts in the margin, gcov style: `-`
+    /// for lines without statements and `#####` for ones never run.
+    pub fn annotated(&self) -> String {
+        let lines = self.lines();
+        let mut out = format!("{:>9} | {}\n", "", self.name);
+        for (idx, text) in self.content.lines().enumerate() {
+            let line = idx + 1;
+            let count = match lines.get(&line) {
+                Some(0) => "#####".to_string(),
+                Some(n) => n.to_string(),
+                None => "-".to_string(),
+            };
+            out.push_str(&format!("{:>9} | {}", count, text));
+
+            let branches: Vec<String> = self.branches.range((line, 0)..(line + 1, 0))
+                .map(|(_, b)| {
+                    let [first, second] = b.kind.labels();
+                    format!(
+                        "{}: {} {}, {} {}",
+                        b.kind.name(),
+                        b.taken[0],
+                        first,
+                        b.taken[1],
+                        second,
+                    )
+                })
+                .collect();
+            if !branches.is_empty() {
+                out.push_str(&format!("    [{}]", branches.join("; ")));
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+
+fn key(pos: &FilePosition) -> (usize, usize) {
+    (pos.lineno, pos.linepos)
+}
+
+
+fn stmt_key(stmt: &Stmt) -> (usize, usize, bool) {
+    (stmt.pos.lineno, stmt.pos.linepos, matches!(stmt.kind, StmtKind::SBlock(_)))
+}
+
+
+/// Statement and branch counts for every loaded source, keyed by
+/// `Source::id`.
+#[derive(Default)]
+pub struct Coverage {
+    pub files: BTreeMap<usize, FileCoverage>,
+}
+
+impl Coverage {
+    pub fn new() -> Rc<RefCell<Coverage>> {
+        Rc::new(RefCell::new(Coverage::default()))
+    }
+
+    pub fn lcov(&self) -> String {
+        self.files.values().map(|f| f.lcov()).collect()
+    }
+
+    pub fn annotated(&self) -> String {
+        let files: Vec<String> = self.files.values().map(|f| f.annotated()).collect();
+        files.join("\n")
+    }
+}
+
+
+pub struct CoverageRecorder(Rc<RefCell<Coverage>>);
+
+impl CoverageRecorder {
+    pub fn new(coverage: &Rc<RefCell<Coverage>>) -> CoverageRecorder {
+        CoverageRecorder(coverage.clone())
+    }
+}
+
+impl Hook for CoverageRecorder {
+    fn loaded(&mut self, src: &Source, ast: &AST) {
+        let mut coverage = self.0.borrow_mut();
+        let file = coverage.files.entry(src.id).or_default();
+        file.name = src.filename.clone();
+        file.content = src.content.clone();
+        for interpretable in ast.top.iter() {
+            match interpretable {
+                Interpretable::IStmt(stmt) => file.add_stmt(stmt),
+                Interpretable::IExpr(expr) => file.add_expr(expr),
+            }
+        }
+    }
+
+    fn before_stmt(&mut self, stmt: &Stmt, _env: &Rc<Environment>) -> Result<(), String> {
+        let mut coverage = self.0.borrow_mut();
+        let file = coverage.files.entry(stmt.pos.source).or_default();
+        *file.stmts.entry(stmt_key(stmt)).or_insert(0) += 1;
+        Ok(())
+    }
+
+    fn branch(&mut self, pos: &FilePosition, branch: usize) {
+        let mut coverage = self.0.borrow_mut();
+        let file = coverage.files.entry(pos.source).or_default();
+        if let Some(b) = file.branches.get_mut(&key(pos)) {
+            b.taken[branch] += 1;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hooks;
+    use crate::interpreter::Interpreter;
+    use pretty_assertions::assert_eq;
+
+    fn cover(code: &str) -> Rc<RefCell<Coverage>> {
+        let coverage = Coverage::new();
+        hooks::install(Box::new(CoverageRecorder::new(&coverage)));
+        Interpreter::new().interpret(&mut Source::from_string(code.to_string())).unwrap();
+        hooks::clear();
+        coverage
+    }
+
+    const CODE: &str = "fun f(n) {
+    if n > 1 {
+        return true or n;
+    }
+    return false;
+}
+var i = 0;
+while i < 3 {
+    f(i);
+    i = i + 1;
+}
+while false {
+    print 1;
+}
+";
+
+    #[test]
+    fn test_lcov() {
+        let coverage = cover(CODE);
+        assert_eq!(coverage.borrow().lcov(), "TN:
+SF:__str__
+BRDA:2,0,0,1
+BRDA:2,0,1,2
+BRDA:3,0,0,0
+BRDA:3,0,1,1
+BRDA:8,0,0,1
+BRDA:8,0,1,0
+BRDA:12,0,0,0
+BRDA:12,0,1,1
+BRF:8
+BRH:5
+DA:1,3
+DA:2,3
+DA:3,1
+DA:5,2
+DA:7,1
+DA:8,3
+DA:9,3
+DA:10,3
+DA:12,1
+DA:13,0
+LF:10
+LH:9
+end_of_record
+");
+    }
+
+    #[test]
+    fn test_annotated() {
+        let coverage = cover(CODE);
+        let annotated = coverage.borrow().annotated();
+        let lines: Vec<&str> = annotated.lines().collect();
+        assert_eq!(lines[2], "        3 |     if n > 1 {    [if: 1 then, 2 else]");
+        assert_eq!(lines[4], "        - |     }");
+        assert_eq!(lines[13], "    ##### |     print 1;");
+    }
+
+    #[test]
+    fn test_for_loop() {
+        let coverage = cover("var n = 0;\nfor (var i = 0; i < 3; i = i + 1) {\n    n = n + i;\n}\n");
+        let coverage = coverage.borrow();
+        let file = coverage.files.values().next().unwrap();
+        assert_eq!(file.lines().into_iter().collect::<Vec<_>>(), vec![(1, 1), (2, 3), (3, 3)]);
+    }
+
+    #[test]
+    fn test_multiple_files() {
+        let dir = std::env::temp_dir();
+        let lib = dir.join("bwl_coverage_lib.lox");
+        let main = dir.join("bwl_coverage_main.lox");
+        std::fs::write(&lib, "fun double(x) {\n    return x * 2;\n}\nfun unused() {\n    return 0;\n}\n").unwrap();
+        std::fs::write(&main, "var y = double(4);\n").unwrap();
+
+        let coverage = Coverage::new();
+        hooks::install(Box::new(CoverageRecorder::new(&coverage)));
+        let mut interpreter = Interpreter::new();
+        for path in [&lib, &main] {
+            let mut src = Source::from_file(path.to_str().unwrap()).unwrap();
+            interpreter.interpret(&mut src).unwrap();
+        }
+        hooks::clear();
+
+        let coverage = coverage.borrow();
+        let files: Vec<&FileCoverage> = coverage.files.values().collect();
+        assert_eq!(files.len(), 2);
+        assert!(files[0].name.ends_with("bwl_coverage_lib.lox"));
+        assert_eq!(files[0].lines().into_iter().collect::<Vec<_>>(), vec![(1, 1), (2, 1), (4, 1), (5, 0)]);
+        assert_eq!(files[1].lines().into_iter().collect::<Vec<_>>(), vec![(1, 1)]);
+    }
+}
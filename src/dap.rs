@@ -0,0 +1,446 @@
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::thread;
+
+use serde_json::{json, Value};
+
+use crate::ast::Stmt;
+use crate::debugger::{self, Debugger, Frontend, PauseReason, Resume, Session};
+use crate::environment::Environment;
+use crate::hooks;
+use crate::interpreter::{InterpretError, Interpreter};
+use crate::source::Source;
+
+
+const THREAD_ID: i64 = 1;
+
+
+fn read_message<R: BufRead>(input: &mut R) -> Option<Value> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let mut body = vec![0; length?];
+    input.read_exact(&mut body).ok()?;
+    serde_json::from_slice(&body).ok()
+}
+
+
+/// The client connection, shared by the server loop and the front end
+/// that runs inside the evaluator while the program executes.
+struct Connection {
+    incoming: Receiver<Value>,
+    output: Box<dyn Write>,
+    seq: i64,
+    disconnected: bool,
+}
+
+impl Connection {
+    fn send(&mut self, mut msg: Value) {
+        self.seq += 1;
+        msg["seq"] = json!(self.seq);
+        let body = msg.to_string();
+        let _ = write!(self.output, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let _ = self.output.flush();
+    }
+
+    fn respond(&mut self, request: &Value, body: Value) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": true,
+            "body": body,
+        }));
+    }
+
+    fn fail(&mut self, request: &Value, message: &str) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": false,
+            "message": message,
+        }));
+    }
+
+    fn event(&mut self, event: &str, body: Value) {
+        self.send(json!({
+            "type": "event",
+            "event": event,
+            "body": body,
+        }));
+    }
+
+    fn next(&mut self) -> Option<Value> {
+        self.incoming.recv().ok()
+    }
+}
+
+
+fn set_breakpoints(conn: &mut Connection, request: &Value) -> BTreeSet<usize> {
+    let lines: BTreeSet<usize> = request["arguments"]["breakpoints"]
+        .as_array()
+        .map(|bps| bps.iter().filter_map(|bp| bp["line"].as_u64()).map(|l| l as usize).collect())
+        .unwrap_or_default();
+    let verified: Vec<Value> = lines.iter()
+        .map(|line| json!({ "verified": true, "line": line }))
+        .collect();
+    conn.respond(request, json!({ "breakpoints": verified }));
+    lines
+}
+
+
+/// Handles the requests that are valid at any point of the session,
+/// returning false for anything else.
+fn common_request(conn: &mut Connection, request: &Value) -> bool {
+    match request["command"].as_str().unwrap_or("") {
+        "threads" => conn.respond(request, json!({
+            "threads": [{ "id": THREAD_ID, "name": "main" }],
+        })),
+        "setExceptionBreakpoints" => conn.respond(request, json!({})),
+        "disconnect" | "terminate" => {
+            conn.respond(request, json!({}));
+            conn.disconnected = true;
+        },
+        _ => return false,
+    }
+    true
+}
+
+
+struct Client {
+    conn: Rc<RefCell<Connection>>,
+    path: String,
+    scopes: Vec<Rc<Environment>>,
+}
+
+impl Client {
+    fn stack_trace(&self, session: &Session) -> Value {
+        let frames: Vec<Value> = session.frames.iter()
+            .enumerate()
+            .rev()
+            .map(|(idx, frame)| json!({
+                "id": idx,
+                "name": frame.name,
+                "line": frame.line,
+                "column": 1,
+                "source": { "path": self.path },
+            }))
+            .collect();
+        json!({ "stackFrames": frames, "totalFrames": frames.len() })
+    }
+
+    fn frame_env(session: &Session, request: &Value) -> Option<Rc<Environment>> {
+        let frame = match request["arguments"]["frameId"].as_u64() {
+            Some(idx) => session.frames.get(idx as usize)?,
+            None => session.current(),
+        };
+        frame.env.clone()
+    }
+
+    // Each non-empty scope of the frame's environment chain is exposed as
+    // a DAP scope; its variablesReference is its index in `self.scopes`.
+    fn scopes(&mut self, env: Option<Rc<Environment>>) -> Value {
+        let mut scopes = Vec::new();
+        let mut env = env;
+        while let Some(scope) = env {
+            let name = match (scope.parent(), scopes.len()) {
+                (None, _) => "Globals".to_string(),
+                (Some(_), 0) => "Locals".to_string(),
+                (Some(_), n) => format!("Enclosing {}", n),
+            };
+            if scope.parent().is_none() || !scope.bindings().is_empty() {
+                self.scopes.push(scope.clone());
+                scopes.push(json!({
+                    "name": name,
+                    "variablesReference": self.scopes.len(),
+                    "expensive": false,
+                }));
+            }
+            env = scope.parent().cloned();
+        }
+        json!({ "scopes": scopes })
+    }
+
+    fn variables(&self, request: &Value) -> Value {
+        let reference = request["arguments"]["variablesReference"].as_u64().unwrap_or(0) as usize;
+        let variables: Vec<Value> = match self.scopes.get(reference.wrapping_sub(1)) {
+            Some(scope) => scope.script_bindings().into_iter()
+                .map(|(name, value)| json!({
+                    "name": name,
+                    "value": match &value {
+                        Some(v) => v.value_string(),
+                        None => "<uninitialized>".to_string(),
+                    },
+                    "type": match &value {
+                        Some(v) => format!("{}", **v),
+                        None => "Nil".to_string(),
+                    },
+                    "variablesReference": 0,
+                }))
+                .collect(),
+            None => Vec::new(),
+        };
+        json!({ "variables": variables })
+    }
+}
+
+impl Frontend for Client {
+    fn paused(&mut self, session: &mut Session, reason: PauseReason, _stmt: &Stmt) -> Resume {
+        let conn = self.conn.clone();
+        let mut conn = conn.borrow_mut();
+        conn.event("stopped", json!({
+            "reason": match reason {
+                PauseReason::Entry => "entry",
+                PauseReason::Breakpoint => "breakpoint",
+                PauseReason::Step => "step",
+                PauseReason::Pause => "pause",
+            },
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        }));
+        self.scopes.clear();
+
+        while let Some(request) = conn.next() {
+            let resume = match request["command"].as_str().unwrap_or("") {
+                "continue" => Some(Resume::Continue),
+                "next" => Some(Resume::StepOver),
+                "stepIn" => Some(Resume::StepIn),
+                "stepOut" => Some(Resume::StepOut),
+                _ => None,
+            };
+            if let Some(resume) = resume {
+                conn.respond(&request, json!({ "allThreadsContinued": true }));
+                return resume;
+            }
+
+            match request["command"].as_str().unwrap_or("") {
+                "stackTrace" => conn.respond(&request, self.stack_trace(session)),
+                "scopes" => {
+                    let env = Client::frame_env(session, &request);
+                    conn.respond(&request, self.scopes(env));
+                },
+                "variables" => conn.respond(&request, self.variables(&request)),
+                "evaluate" => {
+                    let code = request["arguments"]["expression"].as_str().unwrap_or("");
+                    let result = match Client::frame_env(session, &request) {
+                        Some(env) => debugger::evaluate(code, &env),
+                        None => Err("no frame to evaluate in".to_string()),
+                    };
+                    match result {
+                        Ok(v) => conn.respond(&request, json!({
+                            "result": v.value_string(),
+                            "type": format!("{}", *v),
+                            "variablesReference": 0,
+                        })),
+                        Err(e) => conn.fail(&request, &e),
+                    }
+                },
+                "setBreakpoints" => session.breakpoints = set_breakpoints(&mut conn, &request),
+                "pause" => conn.respond(&request, json!({})),
+                _ => {
+                    if !common_request(&mut conn, &request) {
+                        conn.fail(&request, "unsupported request while paused");
+                    }
+                },
+            }
+
+            if conn.disconnected {
+                return Resume::Quit;
+            }
+        }
+
+        Resume::Quit
+    }
+
+    fn interrupt(&mut self, session: &mut Session) -> bool {
+        let mut conn = self.conn.borrow_mut();
+        loop {
+            let request = match conn.incoming.try_recv() {
+                Ok(request) => request,
+                Err(TryRecvError::Empty) => return false,
+                Err(TryRecvError::Disconnected) => {
+                    conn.disconnected = true;
+                    return true;
+                },
+            };
+
+            match request["command"].as_str().unwrap_or("") {
+                "pause" => {
+                    conn.respond(&request, json!({}));
+                    return true;
+                },
+                "setBreakpoints" => session.breakpoints = set_breakpoints(&mut conn, &request),
+                _ => {
+                    if !common_request(&mut conn, &request) {
+                        conn.fail(&request, "unsupported request while running");
+                    }
+                },
+            }
+
+            if conn.disconnected {
+                return true;
+            }
+        }
+    }
+
+    fn output(&mut self, text: &str) -> bool {
+        self.conn.borrow_mut().event("output", json!({
+            "category": "stdout",
+            "output": format!("{}\n", text),
+        }));
+        true
+    }
+}
+
+
+/// Serves a single debugging session, reading requests from `input` and
+/// writing responses and events to `output`, until the client disconnects.
+pub fn serve<R, W>(mut input: R, output: W)
+where
+    R: BufRead + Send + 'static,
+    W: Write + 'static,
+{
+    let (sender, incoming) = channel();
+    thread::spawn(move || {
+        while let Some(msg) = read_message(&mut input) {
+            if sender.send(msg).is_err() {
+                break;
+            }
+        }
+    });
+
+    let conn = Rc::new(RefCell::new(Connection {
+        incoming,
+        output: Box::new(output),
+        seq: 0,
+        disconnected: false,
+    }));
+
+    let mut program = None;
+    let mut stop_on_entry = false;
+    let mut breakpoints = BTreeSet::new();
+
+    // Configuration phase: everything up to configurationDone.
+    loop {
+        let mut c = conn.borrow_mut();
+        let Some(request) = c.next() else { return };
+        match request["command"].as_str().unwrap_or("") {
+            "initialize" => {
+                c.respond(&request, json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsEvaluateForHovers": true,
+                    "supportsTerminateRequest": true,
+                }));
+                c.event("initialized", json!({}));
+            },
+            "launch" => {
+                let args = &request["arguments"];
+                match args["program"].as_str() {
+                    Some(path) => {
+                        program = Some(path.to_string());
+                        stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+                        c.respond(&request, json!({}));
+                    },
+                    None => c.fail(&request, "launch requires a 'program' argument"),
+                }
+            },
+            "setBreakpoints" => breakpoints = set_breakpoints(&mut c, &request),
+            "configurationDone" => {
+                c.respond(&request, json!({}));
+                break;
+            },
+            _ => {
+                if !common_request(&mut c, &request) {
+                    c.fail(&request, "unsupported request before configurationDone");
+                }
+            },
+        }
+        if c.disconnected {
+            return;
+        }
+    }
+
+    let exit_code = match program {
+        Some(path) => run(&conn, &path, stop_on_entry, breakpoints),
+        None => {
+            conn.borrow_mut().event("output", json!({
+                "category": "stderr",
+                "output": "no program was launched\n",
+            }));
+            1
+        },
+    };
+
+    let mut c = conn.borrow_mut();
+    c.event("exited", json!({ "exitCode": exit_code }));
+    c.event("terminated", json!({}));
+
+    while !c.disconnected {
+        let Some(request) = c.next() else { break };
+        if !common_request(&mut c, &request) {
+            c.fail(&request, "program has terminated");
+        }
+    }
+}
+
+
+fn run(
+    conn: &Rc<RefCell<Connection>>,
+    path: &str,
+    stop_on_entry: bool,
+    breakpoints: BTreeSet<usize>,
+) -> i64 {
+    let mut src = match Source::from_file(path) {
+        Ok(src) => src,
+        Err(e) => {
+            conn.borrow_mut().event("output", json!({
+                "category": "stderr",
+                "output": format!("{}\n", e),
+            }));
+            return 1;
+        },
+    };
+
+    let client = Client {
+        conn: conn.clone(),
+        path: path.to_string(),
+        scopes: Vec::new(),
+    };
+    hooks::install(Box::new(
+        Debugger::new(client)
+            .stop_on_entry(stop_on_entry)
+            .breakpoints(breakpoints),
+    ));
+    let result = Interpreter::new().interpret(&mut src);
+    hooks::clear();
+
+    match result {
+        Ok(_) => 0,
+        Err(InterpretError::Runtime(e)) if e == debugger::QUIT => 0,
+        Err(e) => {
+            conn.borrow_mut().event("output", json!({
+                "category": "stderr",
+                "output": format!("{}\n", e),
+            }));
+            e.exit_code() as i64
+        },
+    }
+}
@@ -0,0 +1,499 @@
+use std::collections::BTreeSet;
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+
+use crate::ast::{params_string, Stmt};
+use crate::environment::Environment;
+use crate::evaluator::eval;
+use crate::hooks::Hook;
+use crate::parser::parse_expr;
+use crate::source::Source;
+use crate::tokenizer::tokenize;
+use crate::value::{LoxType, LoxValue};
+
+
+/// Error message used to unwind the evaluator when the user quits.
+pub const QUIT: &str = "DebuggerQuit: program terminated by debugger";
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PauseReason {
+    Entry,
+    Breakpoint,
+    Step,
+    Pause,
+}
+
+
+/// What a front end asks the debugger to do once it resumes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Resume {
+    Continue,
+    StepIn,
+    StepOver,
+    StepOut,
+    Quit,
+}
+
+
+#[derive(Clone, Debug)]
+pub struct Frame {
+    pub name: String,
+    pub line: usize,
+    pub env: Option<Rc<Environment>>,
+    // the statements run since control reached `line`
+    passed: Vec<*const Stmt>,
+}
+
+impl Frame {
+    fn new(name: &str) -> Frame {
+        Frame {
+            name: name.to_string(),
+            line: 0,
+            env: None,
+            passed: Vec::new(),
+        }
+    }
+}
+
+
+/// Breakpoints, call stack and stepping state shared by all front ends.
+pub struct Session {
+    pub breakpoints: BTreeSet<usize>,
+    pub frames: Vec<Frame>,
+    resume: Resume,
+    target_depth: usize,
+    last_line: usize,
+}
+
+impl Session {
+    fn new() -> Session {
+        Session {
+            breakpoints: BTreeSet::new(),
+            frames: vec![Frame::new("<script>")],
+            resume: Resume::StepIn,
+            target_depth: 0,
+            last_line: 0,
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// The innermost frame, i.e. the one currently executing.
+    pub fn current(&self) -> &Frame {
+        self.frames.last().expect("debugger call stack is never empty")
+    }
+
+    fn current_mut(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("debugger call stack is never empty")
+    }
+
+    fn resume(&mut self, resume: Resume) {
+        self.resume = resume;
+        self.target_depth = self.depth();
+    }
+
+    fn pause_reason(&self, stmt: &Stmt) -> Option<PauseReason> {
+        let stepped = match self.resume {
+            Resume::StepIn => true,
+            Resume::StepOver => self.depth() <= self.target_depth,
+            Resume::StepOut => self.depth() < self.target_depth,
+            Resume::Continue | Resume::Quit => false,
+        };
+
+        if stepped {
+            return Some(match self.last_line {
+                0 => PauseReason::Entry,
+                _ => PauseReason::Step,
+            });
+        }
+
+        // once per pass over a line, so statements after the first on it
+        // don't pause again, but loops and calls back into it do
+        let frame = self.current();
+        let passing = frame.line == stmt.line() && !frame.passed.contains(&(stmt as *const Stmt));
+        if self.breakpoints.contains(&stmt.line()) && !passing {
+            return Some(PauseReason::Breakpoint);
+        }
+
+        None
+    }
+}
+
+
+/// A user interface driving a debugging session.
+pub trait Frontend {
+    fn paused(&mut self, session: &mut Session, reason: PauseReason, stmt: &Stmt) -> Resume;
+
+    /// Polled before each statement while running; returning true pauses
+    /// the program as soon as possible.
+    fn interrupt(&mut self, _session: &mut Session) -> bool {
+        false
+    }
+
+    fn output(&mut self, _text: &str) -> bool {
+        false
+    }
+}
+
+
+pub struct Debugger<F: Frontend> {
+    session: Session,
+    frontend: F,
+}
+
+impl<F: Frontend> Debugger<F> {
+    pub fn new(frontend: F) -> Debugger<F> {
+        Debugger {
+            session: Session::new(),
+            frontend,
+        }
+    }
+
+    /// Whether to pause before the first statement, the default, or run
+    /// until a breakpoint is hit.
+    pub fn stop_on_entry(mut self, stop: bool) -> Debugger<F> {
+        self.session.resume = match stop {
+            true => Resume::StepIn,
+            false => Resume::Continue,
+        };
+        self
+    }
+
+    pub fn breakpoints(mut self, lines: impl IntoIterator<Item = usize>) -> Debugger<F> {
+        self.session.breakpoints.extend(lines);
+        self
+    }
+}
+
+impl<F: Frontend> Hook for Debugger<F> {
+    fn before_stmt(&mut self, stmt: &Stmt, env: &Rc<Environment>) -> Result<(), String> {
+        let line = stmt.line();
+        let mut reason = self.session.pause_reason(stmt);
+        {
+            let frame = self.session.current_mut();
+            if frame.line != line || frame.passed.contains(&(stmt as *const Stmt)) {
+                frame.passed.clear();
+            }
+            frame.passed.push(stmt);
+            frame.line = line;
+            frame.env = Some(env.clone());
+        }
+
+        if reason.is_none() && self.frontend.interrupt(&mut self.session) {
+            reason = Some(PauseReason::Pause);
+        }
+
+        if let Some(reason) = reason {
+            let resume = self.frontend.paused(&mut self.session, reason, stmt);
+            if resume == Resume::Quit {
+                return Err(QUIT.to_string());
+            }
+            self.session.resume(resume);
+        }
+
+        self.session.last_line = line;
+        Ok(())
+    }
+
+    fn enter_call(&mut self, func: &LoxValue, _args: &[LoxValue]) -> Result<(), String> {
+        self.session.frames.push(Frame::new(&func.value_string()));
+        Ok(())
+    }
+
+    fn exit_call(&mut self, _func: &LoxValue, _result: Option<&LoxValue>) {
+        if self.session.depth() > 1 {
+            self.session.frames.pop();
+        }
+    }
+
+    fn print(&mut self, text: &str) -> bool {
+        self.frontend.output(text)
+    }
+}
+
+
+/// Evaluates an expression in the scope of a paused frame.
+pub fn evaluate(code: &str, env: &Rc<Environment>) -> Result<LoxValue, String> {
+    let src = Source::from_string(code.to_string());
+    let tokens = tokenize(&src).map_err(|e| src.format_error(&e))?;
+    let expr = parse_expr(&tokens).map_err(|e| src.format_error(&e))?;
+    eval(&expr, env)
+}
+
+
+pub fn describe_value(value: &Option<LoxValue>) -> String {
+    match value {
+        Some(v) => match &**v {
+            LoxType::VStr(s) => format!("\"{}\" ({})", s, **v),
+            LoxType::VCallable(name, params, ..) => format!("<fun {}({})>", name, params_string(params)),
+            LoxType::VNative(native) => format!("<native {}>", native.name),
+            _ => format!("{} ({})", v.value_string(), **v),
+        },
+        None => "<uninitialized>".to_string(),
+    }
+}
+
+
+/// Line-oriented debugger front end for terminals.
+pub struct Console<R: BufRead, W: Write> {
+    input: R,
+    output: W,
+    filename: String,
+    lines: Vec<String>,
+}
+
+const HELP: &str = "\
+Commands:
+  b|break LINE      set a breakpoint on LINE
+  d|delete LINE     remove the breakpoint on LINE
+  i|info            list breakpoints
+  s|step            step into the next statement
+  n|next            step over calls to the next statement
+  o|out             run until the current function returns
+  c|continue        run until the next breakpoint
+  e|env             print the environment chain of the current frame
+  p|print EXPR      evaluate EXPR in the current frame
+  bt|stack          print the call stack
+  l|list            show the source around the current line
+  q|quit            stop the program
+  h|help            show this message";
+
+impl<R: BufRead, W: Write> Console<R, W> {
+    pub fn new(src: &Source, input: R, output: W) -> Console<R, W> {
+        Console {
+            input,
+            output,
+            filename: src.filename.clone(),
+            lines: src.content.lines().map(|l| l.to_string()).collect(),
+        }
+    }
+
+    fn source_line(&self, line: usize) -> &str {
+        match line {
+            0 => "",
+            n => self.lines.get(n - 1).map(|l| l.as_str()).unwrap_or(""),
+        }
+    }
+
+    fn show_location(&mut self, line: usize) {
+        let text = self.source_line(line).to_string();
+        let _ = writeln!(self.output, "{}:{}: {}", self.filename, line, text.trim());
+    }
+
+    fn list(&mut self, line: usize) {
+        let start = line.saturating_sub(3).max(1);
+        let end = (line + 3).min(self.lines.len());
+        for n in start..=end {
+            let marker = if n == line { "->" } else { "  " };
+            let text = self.source_line(n).to_string();
+            let _ = writeln!(self.output, "{} {:>4} {}", marker, n, text);
+        }
+    }
+
+    fn print_env(&mut self, session: &Session) {
+        let mut env = session.current().env.clone();
+        let mut depth = 0;
+        while let Some(scope) = env {
+            let bindings = scope.script_bindings();
+            let label = match scope.parent() {
+                Some(_) if bindings.is_empty() => None,
+                Some(_) => Some(format!("scope {}", depth)),
+                None => Some("globals".to_string()),
+            };
+            if let Some(label) = label {
+                let _ = writeln!(self.output, "{}:", label);
+            }
+            for (name, value) in bindings {
+                let _ = writeln!(self.output, "    {} = {}", name, describe_value(&value));
+            }
+            env = scope.parent().cloned();
+            depth += 1;
+        }
+    }
+
+    fn print_stack(&mut self, session: &Session) {
+        for (idx, frame) in session.frames.iter().enumerate().rev() {
+            let _ = writeln!(self.output, "#{} {} at line {}", idx, frame.name, frame.line);
+        }
+    }
+
+    fn set_breakpoint(&mut self, session: &mut Session, arg: &str, set: bool) {
+        let line: usize = match arg.trim().parse() {
+            Ok(v) => v,
+            Err(_) => {
+                let _ = writeln!(self.output, "expected a line number, got '{}'", arg.trim());
+                return;
+            },
+        };
+        if set {
+            session.breakpoints.insert(line);
+            let _ = writeln!(self.output, "breakpoint set at line {}", line);
+        } else if session.breakpoints.remove(&line) {
+            let _ = writeln!(self.output, "breakpoint at line {} removed", line);
+        } else {
+            let _ = writeln!(self.output, "no breakpoint at line {}", line);
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> Frontend for Console<R, W> {
+    fn paused(&mut self, session: &mut Session, reason: PauseReason, _stmt: &Stmt) -> Resume {
+        let line = session.current().line;
+        if reason == PauseReason::Breakpoint {
+            let _ = writeln!(self.output, "breakpoint hit");
+        }
+        self.show_location(line);
+
+        loop {
+            let _ = write!(self.output, "(bwl-dbg) ");
+            let _ = self.output.flush();
+
+            let mut input = String::new();
+            match self.input.read_line(&mut input) {
+                Ok(0) | Err(_) => return Resume::Quit,
+                Ok(_) => (),
+            }
+
+            let input = input.trim();
+            let (cmd, arg) = match input.split_once(' ') {
+                Some((cmd, arg)) => (cmd, arg),
+                None => (input, ""),
+            };
+
+            match cmd {
+                "" => continue,
+                "b" | "break" => self.set_breakpoint(session, arg, true),
+                "d" | "delete" => self.set_breakpoint(session, arg, false),
+                "i" | "info" => {
+                    let lines: Vec<String> = session.breakpoints.iter()
+                        .map(|l| l.to_string())
+                        .collect();
+                    let _ = writeln!(self.output, "breakpoints: {}", lines.join(", "));
+                },
+                "s" | "step" => return Resume::StepIn,
+                "n" | "next" => return Resume::StepOver,
+                "o" | "out" => return Resume::StepOut,
+                "c" | "continue" => return Resume::Continue,
+                "q" | "quit" => return Resume::Quit,
+                "e" | "env" => self.print_env(session),
+                "bt" | "stack" => self.print_stack(session),
+                "l" | "list" => self.list(line),
+                "p" | "print" => {
+                    let result = match &session.current().env {
+                        Some(env) => evaluate(arg, env),
+                        None => Err("no frame to evaluate in".to_string()),
+                    };
+                    let _ = match result {
+                        Ok(v) => writeln!(self.output, "{}", describe_value(&Some(v))),
+                        Err(e) => writeln!(self.output, "{}", e),
+                    };
+                },
+                "h" | "help" => {
+                    let _ = writeln!(self.output, "{}", HELP);
+                },
+                other => {
+                    let _ = writeln!(self.output, "unknown command '{}', try 'help'", other);
+                },
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hooks;
+    use crate::interpreter::{InterpretError, Interpreter};
+    use std::cell::RefCell;
+    use std::io::Cursor;
+
+    #[derive(Clone)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn debug(code: &str, commands: &str) -> (Result<Option<String>, InterpretError>, String) {
+        let mut src = Source::from_string(code.to_string());
+        let output = Shared(Rc::new(RefCell::new(Vec::new())));
+        let console = Console::new(&src, Cursor::new(commands.to_string()), output.clone());
+        hooks::install(Box::new(Debugger::new(console)));
+        let result = Interpreter::new().interpret(&mut src);
+        hooks::clear();
+        let output = String::from_utf8(output.0.borrow().clone()).unwrap();
+        (result, output)
+    }
+
+    const FACT: &str = "fun fact(n) {
+    if n == 1 {
+        return 1;
+    }
+    return n * fact(n - 1);
+}
+var x = fact(3);
+var y = x + 1;
+";
+
+    #[test]
+    fn test_pauses_on_entry() {
+        let (result, output) = debug(FACT, "c\n");
+        assert!(result.is_ok());
+        assert!(output.starts_with("__str__:1: fun fact(n) {"));
+    }
+
+    #[test]
+    fn test_breakpoint_and_stack() {
+        let (_, output) = debug(FACT, "b 3\nc\nbt\nc\n");
+        assert!(output.contains("breakpoint hit\n__str__:3: return 1;"));
+        assert!(output.contains("#3 fact at line 3\n#2 fact at line 5\n#1 fact at line 5\n#0 <script> at line 7"));
+    }
+
+    #[test]
+    fn test_breakpoint_on_one_line_loop_and_recursion() {
+        let code = "var i = 0;\nwhile i < 3 { i = i + 1; }\nprint i;\n";
+        let (_, output) = debug(code, "b 2\nc\nc\nc\nc\n");
+        assert_eq!(output.matches("breakpoint hit").count(), 3);
+
+        let code = "fun down(n) { if n > 0 { down(n - 1); } }\ndown(2);\n";
+        let (_, output) = debug(code, "b 1\nc\nc\nc\nbt\nc\n");
+        assert_eq!(output.matches("breakpoint hit").count(), 3);
+        assert!(output.contains("#3 down at line 1\n#2 down at line 1"));
+
+        let code = "var n = 0;\nfor (var i = 0; i < 3; i = i + 1) { n = n + i; }\n";
+        let (_, output) = debug(code, "b 2\nc\nc\nc\nc\n");
+        assert_eq!(output.matches("breakpoint hit").count(), 3);
+    }
+
+    #[test]
+    fn test_print_and_env() {
+        let (_, output) = debug(FACT, "b 3\nc\np n * 10\ne\nc\n");
+        assert!(output.contains("10 (Int)"));
+        assert!(output.contains("(bwl-dbg) scope 2:\n    n = 1 (Int)\nglobals:\n    fact = <fun fact(n)>"));
+    }
+
+    #[test]
+    fn test_step_over_and_out() {
+        let (_, output) = debug(FACT, "n\nn\nn\nc\n");
+        assert!(output.contains("__str__:7: var x = fact(3);\n(bwl-dbg) __str__:8: var y = x + 1;"));
+
+        let (_, output) = debug(FACT, "n\ns\ns\no\nc\n");
+        assert!(output.contains("__str__:2: if n == 1 {\n(bwl-dbg) __str__:8: var y = x + 1;"));
+    }
+
+    #[test]
+    fn test_quit() {
+        let (result, _) = debug(FACT, "q\n");
+        assert_eq!(result, Err(InterpretError::Runtime(QUIT.to_string())));
+    }
+}
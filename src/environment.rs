@@ -2,7 +2,7 @@ use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
 
-use crate::value::LoxValue;
+use crate::value::{LoxType, LoxValue};
 
 
 #[derive(Clone, Debug, PartialEq)]
@@ -26,6 +26,28 @@ impl Environment {
         })
     }
 
+    pub fn parent(&self) -> Option<&Rc<Environment>> {
+        self.parent.as_ref()
+    }
+
+    /// The names declared directly in this scope, sorted by name.
+    pub fn bindings(&self) -> Vec<(String, Option<LoxValue>)> {
+        let mut bindings: Vec<_> = self.env.borrow()
+            .iter()
+            .map(|(name, val)| (name.clone(), val.clone()))
+            .collect();
+        bindings.sort_by(|a, b| a.0.cmp(&b.0));
+        bindings
+    }
+
+    /// `bindings`, without native functions like the builtin `int` that
+    /// every program has.
+    pub fn script_bindings(&self) -> Vec<(String, Option<LoxValue>)> {
+        self.bindings().into_iter()
+            .filter(|(_, value)| !matches!(value.as_deref(), Some(LoxType::VNative(_))))
+            .collect()
+    }
+
     pub fn var(&self, name: &str, val: Option<LoxValue>) -> Option<LoxValue> {
         self.env.borrow_mut().insert(name.to_string(), val.clone());
         val.clone()
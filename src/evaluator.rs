@@ -2,8 +2,10 @@ use std::rc::Rc;
 
 use crate::ast::Interpretables;
 
-use super::ast::{Expr, Stmt, Interpretable, Operator};
+use super::ast::{Expr, Param, Stmt, StmtKind, Interpretable, Operator};
 use super::environment::Environment;
+use super::source::FilePosition;
+use super::hooks;
 use super::value::{LoxValue, LoxType};
 
 
@@ -18,6 +20,7 @@ fn eval_bin_op(
         Add => left.add(&right),
         Mul => left.mul(&right),
         Div => left.div(&right),
+        Mod => left.rem(right),
         NotEqual => left.neq(&right),
         Equal => left.eq(&right),
         Greater => left.gt(&right),
@@ -29,20 +32,6 @@ fn eval_bin_op(
 }
 
 
-fn eval_logical_op(
-    op: &Operator,
-    left: &LoxValue,
-    right: &LoxValue,
-) -> Result<LoxValue, String> {
-    use Operator::*;
-    match op {
-        And => left.and(&right),
-        Or => left.or(&right),
-        _ => Err(format!("Unsupported logical operation: {}", op)),
-    }
-}
-
-
 fn eval_unary_op(
     op: &Operator,
     operand: &LoxValue,
@@ -56,10 +45,225 @@ fn eval_unary_op(
 }
 
 
+fn arity_error(func: &LoxValue, min: usize, max: Option<usize>, got: usize) -> String {
+    let accepted = match max {
+        Some(max) if max == min => format!("{}", min),
+        Some(max) => format!("{} to {}", min, max),
+        None => format!("at least {}", min),
+    };
+    format!("Function {} requires {} argument(s), got {}", func.value_string(), accepted, got)
+}
+
+
+fn at(pos: &FilePosition) -> String {
+    format!("at line {}, column {}", pos.lineno, pos.linepos)
+}
+
+
+// Matches the positional and named arguments of the call at `pos` to
+// `params`, giving the value of each parameter, or None for one to take
+// its default.
+fn match_args(
+    func: &LoxValue,
+    params: &[Param],
+    positional: Vec<LoxValue>,
+    named: Vec<(&str, &FilePosition, LoxValue)>,
+    pos: &FilePosition,
+) -> Result<Vec<Option<LoxValue>>, String> {
+    let rest = params.last().is_some_and(|p| p.rest);
+    let fixed = params.len() - rest as usize;
+    let min = params.iter().filter(|p| p.default.is_none() && !p.rest).count();
+    if (positional.len() > fixed && !rest) || (positional.len() < min && named.is_empty()) {
+        return Err(arity_error(func, min, (!rest).then_some(fixed), positional.len()));
+    }
+
+    let mut positional = positional.into_iter();
+    let mut values: Vec<Option<LoxValue>> = positional.by_ref().take(fixed).map(Some).collect();
+    values.resize(fixed, None);
+    if rest {
+        values.push(Some(LoxValue::new(LoxType::VList(positional.collect()))));
+    }
+
+    for (name, name_pos, value) in named {
+        let Some(idx) = params[..fixed].iter().position(|p| p.name == name) else {
+            return Err(format!(
+                "Function {} has no parameter named '{}' {}",
+                func.value_string(),
+                name,
+                at(name_pos),
+            ));
+        };
+        if values[idx].is_some() {
+            return Err(format!("Argument '{}' is given more than once {}", name, at(name_pos)));
+        }
+        values[idx] = Some(value);
+    }
+
+    let missing = params.iter().zip(values.iter()).find(|(p, v)| v.is_none() && p.default.is_none());
+    if let Some((param, _)) = missing {
+        return Err(format!(
+            "Function {} is missing argument '{}' {}",
+            func.value_string(),
+            param.name,
+            at(pos),
+        ));
+    }
+    Ok(values)
+}
+
+
+// Defines `params` in `env` with the `values` of `match_args`, evaluating
+// defaults in `env` so they can refer to earlier parameters.
+fn bind_params(params: &[Param], values: Vec<Option<LoxValue>>, env: &Rc<Environment>) -> Result<(), String> {
+    for (param, value) in params.iter().zip(values) {
+        let value = match (value, &param.default) {
+            (Some(value), _) => value,
+            (None, Some(default)) => eval(default, env)?,
+            (None, None) => unreachable!("missing arguments are checked before binding"),
+        };
+        env.var(&param.name, Some(value));
+    }
+    Ok(())
+}
+
+
+/// A call with its arguments evaluated and matched to the parameters.
+pub struct Call {
+    pub func: LoxValue,
+    /// The arguments as passed, for hooks.
+    pub passed: Vec<LoxValue>,
+    /// The value of each parameter, or None for one to take its default.
+    pub values: Vec<Option<LoxValue>>,
+}
+
+
+/// Checks that `func` can be called, before its arguments are evaluated.
+pub fn check_callable(func: &LoxValue) -> Result<(), String> {
+    match &**func {
+        LoxType::VCallable(..) | LoxType::VNative(_) => Ok(()),
+        _ => Err(format!("{:?} not a function", func)),
+    }
+}
+
+
+/// Matches the evaluated arguments of the call of `func` at `pos` to its
+/// parameters.
+pub fn match_call(
+    func: LoxValue,
+    positional: Vec<LoxValue>,
+    named: Vec<(&str, &FilePosition, LoxValue)>,
+    pos: &FilePosition,
+) -> Result<Call, String> {
+    use LoxType::*;
+    let passed: Vec<LoxValue> = positional.iter()
+        .chain(named.iter().map(|(_, _, value)| value))
+        .cloned()
+        .collect();
+
+    let values = match &*func {
+        VCallable(_, params, ..) => match_args(&func, params, positional, named, pos)?,
+        VNative(native) if !named.is_empty() => {
+            return Err(format!("Function {} takes no named arguments", native.name));
+        },
+        VNative(native) if positional.len() != native.arity => {
+            return Err(arity_error(&func, native.arity, Some(native.arity), positional.len()));
+        },
+        _ => Vec::new(),
+    };
+    Ok(Call { func, passed, values })
+}
+
+
+fn prepare_call(func: &Expr, args: &[Expr], pos: &FilePosition, env: &Rc<Environment>) -> Result<Call, String> {
+    use Expr::*;
+    let func = eval(func, env)?;
+    check_callable(&func)?;
+
+    let mut arg_vals = Vec::new();
+    let mut named = Vec::new();
+    for arg in args.iter() {
+        match arg {
+            ESpread { expr } => match &*eval(expr, env)? {
+                LoxType::VList(items) => arg_vals.extend(items.iter().cloned()),
+                typ => return Err(format!("Cannot spread {} into arguments", typ)),
+            },
+            ENamed { name, expr, pos } => named.push((name.as_str(), pos, eval(expr, env)?)),
+            arg => arg_vals.push(eval(arg, env)?),
+        }
+    }
+    match_call(func, arg_vals, named, pos)
+}
+
+
+fn call(next: Call) -> Result<LoxValue, String> {
+    call_with(next, |func, values| match &**func {
+        LoxType::VCallable(_, params, body, closure, _) => {
+            let func_env = Environment::new_child(closure);
+            bind_params(params, values, &func_env)?;
+            exec_flow(body, &func_env)
+        },
+        _ => unreachable!("call_with runs natives itself"),
+    })
+}
+
+
+/// Makes `next` and any calls it returns in tail position in a loop, so
+/// that tail recursion runs in constant space, running the bodies of Lox
+/// functions with `run`. The functions that made tail calls exit, for
+/// hooks, when the last call returns; they are only kept while hooks are
+/// installed.
+pub fn call_with<F>(mut next: Call, run: F) -> Result<LoxValue, String>
+where
+    F: Fn(&LoxValue, Vec<Option<LoxValue>>) -> Result<Flow, String>,
+{
+    let mut callers = Vec::new();
+    let result = loop {
+        let Call { func, passed, values } = next;
+        if let Err(e) = hooks::enter_call(&func, &passed) {
+            break Err(e);
+        }
+
+        let flow = match &*func {
+            LoxType::VNative(native) => native.call(&passed).map(Flow::Return),
+            _ => run(&func, values),
+        };
+
+        match flow {
+            Ok(Flow::TailCall(tail)) => {
+                if hooks::active() {
+                    callers.push(func);
+                }
+                next = tail;
+            },
+            Ok(Flow::Return(value)) => {
+                hooks::exit_call(&func, Some(&value));
+                break Ok(value);
+            },
+            Ok(Flow::Next) => {
+                let value = LoxValue::new(LoxType::VNil);
+                hooks::exit_call(&func, Some(&value));
+                break Ok(value);
+            },
+            Err(e) => {
+                hooks::exit_call(&func, None);
+                break Err(e);
+            },
+        }
+    };
+
+    for func in callers.iter().rev() {
+        hooks::exit_call(func, result.as_ref().ok());
+    }
+    result
+}
+
+
 pub fn eval(expr: &Expr, env: &Rc<Environment>) -> Result<LoxValue, String> {
     use Expr::*;
     use LoxType::*;
     match expr {
+        EInt { value } => Ok(LoxValue::new(VInt(*value))),
+        EBigInt { value } => Ok(LoxValue::new(VBigInt(value.clone()))),
         ENumb { value } => Ok(LoxValue::new(VNumb(*value))),
         EStr { value } => Ok(LoxValue::new(VStr(value.to_string()))),
         EBool { value } => Ok(LoxValue::new(VBool(*value))),
@@ -78,43 +282,51 @@ pub fn eval(expr: &Expr, env: &Rc<Environment>) -> Result<LoxValue, String> {
             )?,)
         },
         EGroup { expr } => eval(expr.as_ref(), env),
-        EVar { name } => env.lookup( name ),
-        EAssign { name, expr } => env.assign(
-            name,
-            eval(expr.as_ref(), env)?,
-        ),
-        ELogicalOp { op, left, right } => {
-            Ok(eval_logical_op(
-                &op,
-                &eval(left.as_ref(), env)?,
-                &eval(right.as_ref(), env)?,
-            )?,)
+        EVar { name } => {
+            let value = env.lookup( name )?;
+            hooks::read(name, &value);
+            Ok(value)
         },
-        ECall{ func, args } => {
-            let func = eval(func.as_ref(), env)?;
-
-            let VCallable(_, params, body, _env) = &*func else {
-                return Err(format!("{:?} not a function", func));
+        EAssign { name, expr } => {
+            let value = env.assign(
+                name,
+                eval(expr.as_ref(), env)?,
+            )?;
+            hooks::write(name, Some(&value));
+            Ok(value)
+        },
+        ELogicalOp { op, left, right, pos } => {
+            let left = eval(left.as_ref(), env)?;
+            let decided = match op {
+                Operator::Or => left._is_truthy(),
+                Operator::And => !left._is_truthy(),
+                _ => return Err(format!("Unsupported logical operation: {}", op)),
             };
+            hooks::branch(pos, decided as usize);
 
-            if args.len() != params.len() {
-                return Err(format!("Function {:?} requires {} argument(s)", func, params.len()));
+            // the left operand decides the result if it is truthy for `or`
+            // or falsey for `and`; otherwise the result is the right operand
+            if decided {
+                return Ok(left);
             }
-
-            let mut arg_vals = Vec::new();
-            for arg in args.iter() {
-                arg_vals.push(eval(arg, env)?);
-            }
-
-            let func_env = Environment::new_child(&_env);
-            for (parm, arg) in params.iter().zip(arg_vals) {
-                func_env.var(&parm, Some(arg));
-            }
-
-            match exec(&body, &func_env)? {
-                Some(v) => Ok(v),
-                None => Ok(LoxValue::new(VNil)),
+            eval(right.as_ref(), env)
+        },
+        ECall{ func, args, pos } => call(prepare_call(func, args, pos, env)?),
+        EFun { params, body, pos } => Ok(LoxValue::new(VCallable(
+            String::from("lambda"),
+            params.clone(),
+            body.clone(),
+            Environment::new_child(env),
+            *pos,
+        ))),
+        ESpread { .. } => Err("Spread is only allowed in call arguments".to_string()),
+        ENamed { .. } => Err("Named arguments are only allowed in calls".to_string()),
+        EInterpolate { parts } => {
+            let mut value = String::new();
+            for part in parts.iter() {
+                value.push_str(&eval(part, env)?.value_string());
             }
+            Ok(LoxValue::new(VStr(value)))
         },
     }
 }
@@ -128,11 +340,41 @@ fn _add_option<T, E>(x: Result<T, E>) -> Result<Option<T>, E> {
 }
 
 
+/// How a statement finished.
+pub enum Flow {
+    Next,
+    Return(LoxValue),
+    /// Returning the result of a call, which `call` makes in place of the
+    /// function returning it.
+    TailCall(Call),
+}
+
+
 pub fn exec(stmt: &Stmt, env: &Rc<Environment>) -> Result<Option<LoxValue>, String> {
-    use Stmt::*;
-    match stmt {
+    match exec_flow(stmt, env)? {
+        Flow::Next => Ok(None),
+        Flow::Return(value) => Ok(Some(value)),
+        Flow::TailCall(tail) => _add_option(call(tail)),
+    }
+}
+
+
+fn exec_flow(stmt: &Stmt, env: &Rc<Environment>) -> Result<Flow, String> {
+    hooks::before_stmt(stmt, env)?;
+    let result = exec_stmt(stmt, env);
+    hooks::after_stmt(stmt);
+    result
+}
+
+
+fn exec_stmt(stmt: &Stmt, env: &Rc<Environment>) -> Result<Flow, String> {
+    use StmtKind::*;
+    match &stmt.kind {
         SPrint(expr) => {
-            println!("{}", eval(expr, env)?.value_string());
+            let text = eval(expr, env)?.value_string();
+            if !hooks::print(&text) {
+                println!("{}", text);
+            }
         },
         SExpr(expr) => {
             eval(expr, env)?;
@@ -143,47 +385,63 @@ pub fn exec(stmt: &Stmt, env: &Rc<Environment>) -> Result<Option<LoxValue>, Stri
                 None => None,
 
             };
+            hooks::write(name, value.as_ref());
             env.var(name, value);
         },
         SBlock(stmts) => {
             let env = Environment::new_child(env);
             for stmt in stmts{
-                match exec(stmt, &env)? {
-                    Some(v) => return Ok(Some(v)),
-                    None => (),
+                match exec_flow(stmt, &env)? {
+                    Flow::Next => (),
+                    flow => return Ok(flow),
                 }
             }
         },
         SIf(cond, then, else_) => {
-            if eval(cond, &env)?._is_truthy() {
-                return exec(then, &env);
+            if eval(cond, env)?._is_truthy() {
+                hooks::branch(&stmt.pos, 0);
+                return exec_flow(then, env);
             }
 
+            hooks::branch(&stmt.pos, 1);
             if let Some(else_) = else_ {
-                return exec(else_, &env);
+                return exec_flow(else_, env);
             }
         },
         SWhile(cond, body) => {
-            while eval(cond, &env)?._is_truthy() {
-                match exec(body, &env)? {
-                    Some(v) => return Ok(Some(v)),
-                    None => (),
+            let mut entered = false;
+            while eval(cond, env)?._is_truthy() {
+                if !entered {
+                    hooks::branch(&stmt.pos, 0);
+                    entered = true;
+                }
+                match exec_flow(body, env)? {
+                    Flow::Next => (),
+                    flow => return Ok(flow),
                 }
             }
+            if !entered {
+                hooks::branch(&stmt.pos, 1);
+            }
         },
         SFun(name, params, body) => {
             let func = LoxValue::new(LoxType::VCallable(
                 name.clone(),
                 params.clone(),
-                *body.clone(),
+                body.clone(),
                 Environment::new_child(&env),
+                stmt.pos,
             ));
+            hooks::write(name, Some(&func));
             env.var(name, Some(func));
         },
-        SReturn(expr) => return _add_option(eval(expr, env)),
+        SReturn(Expr::ECall { func, args, pos }) => {
+            return Ok(Flow::TailCall(prepare_call(func, args, pos, env)?));
+        },
+        SReturn(expr) => return Ok(Flow::Return(eval(expr, env)?)),
         SEmpty => (),
     }
-    Ok(None)
+    Ok(Flow::Next)
 }
 
 
@@ -219,9 +477,24 @@ mod tests {
         (eval(&expr, &env).unwrap()).clone()
     }
 
+    fn run_program(code: &str) -> Rc<Environment> {
+        let env = Environment::new();
+        let src = crate::source::Source::from_string(code.to_string());
+        let tokens = crate::tokenizer::tokenize(&src).unwrap();
+        let ast = crate::parser::parse(&tokens).unwrap();
+        interpret(&ast.top, &env).unwrap();
+        env
+    }
+
+    fn eval_in(env: &Rc<Environment>, code: &str) -> Result<LoxValue, String> {
+        let src = crate::source::Source::from_string(code.to_string());
+        let tokens = crate::tokenizer::tokenize(&src).unwrap();
+        eval(&crate::parser::parse_expr(&tokens).unwrap(), env)
+    }
+
     #[test]
     fn literals() {
-        assert_eq!(*run_expr("2"), VNumb(2.0));
+        assert_eq!(*run_expr("2"), VInt(2));
         assert_eq!(*run_expr("true"), VBool(true));
         assert_eq!(*run_expr("false"), VBool(false));
         assert_eq!(*run_expr("nil"), VNil);
@@ -230,13 +503,78 @@ mod tests {
 
     #[test]
     fn binops() {
-        assert_eq!(*run_expr("2+3"), VNumb(5.0));
-        assert_eq!(*run_expr("2*3"), VNumb(6.0));
-        assert_eq!(*run_expr("2-3"), VNumb(-1.0));
+        assert_eq!(*run_expr("2+3"), VInt(5));
+        assert_eq!(*run_expr("2*3"), VInt(6));
+        assert_eq!(*run_expr("2-3"), VInt(-1));
         assert_eq!(*run_expr("3/2"), VNumb(1.5));
         assert_eq!(*run_expr("\"hello\"+\"world\""), VStr(String::from("helloworld")));
     }
 
+    #[test]
+    fn ints_and_floats() {
+        assert_eq!(*run_expr("2 + 0.5"), VNumb(2.5));
+        assert_eq!(*run_expr("2.0 * 3"), VNumb(6.0));
+        assert_eq!(*run_expr("4 / 2"), VNumb(2.0));
+        assert_eq!(*run_expr("9007199254740993 + 1"), VInt(9007199254740994));
+        assert_eq!(*run_expr("\"ab\" * 3"), VStr(String::from("ababab")));
+        assert_eq!(*run_expr("1 == 1.0"), VBool(true));
+        assert_eq!(*run_expr("2 < 2.5"), VBool(true));
+        assert_eq!(run_expr("4 / 2").value_string(), "2.0");
+        assert_eq!(run_expr("2 * 1.25").value_string(), "2.5");
+        assert_eq!(run_expr("1e20").value_string(), "100000000000000000000.0");
+        assert_eq!(run_expr("4 / 2 == 2").value_string(), "true");
+
+        let env = Environment::new();
+        assert_eq!(eval_in(&env, "\"ab\" * 1.5"), Err("Cannot multiply String by Float".to_string()));
+        assert_eq!(eval_in(&env, "\"ab\" * -1"), Err("Cannot repeat a String -1 times".to_string()));
+        assert_eq!(
+            eval_in(&env, "\"ab\" * 9223372036854775807"),
+            Err("Cannot repeat a String 9223372036854775807 times".to_string()),
+        );
+    }
+
+    #[test]
+    fn big_ints() {
+        let big = |s: &str| VBigInt(s.parse().unwrap());
+        assert_eq!(*run_expr("9223372036854775807 + 1"), big("9223372036854775808"));
+        assert_eq!(*run_expr("-(-9223372036854775807 - 1)"), big("9223372036854775808"));
+        assert_eq!(*run_expr("4294967296 * 4294967296"), big("18446744073709551616"));
+        assert_eq!(*run_expr("18446744073709551616 - 18446744073709551615"), VInt(1));
+        assert_eq!(*run_expr("-18446744073709551616 % 1000"), VInt(-616));
+        assert_eq!(*run_expr("18446744073709551616 / 4"), VNumb(4611686018427387904.0));
+        assert_eq!(*run_expr("18446744073709551616 > 9223372036854775807"), VBool(true));
+        assert_eq!(*run_expr("18446744073709551616 == 18446744073709551616.0"), VBool(true));
+        assert_eq!(*run_expr("18446744073709551616 + 0.5"), VNumb(18446744073709551616.5));
+        assert_eq!(run_expr("0x1_0000_0000_0000_0000").value_string(), "18446744073709551616");
+    }
+
+    #[test]
+    fn remainder() {
+        assert_eq!(*run_expr("7 % 3"), VInt(1));
+        assert_eq!(*run_expr("-7 % 3"), VInt(-1));
+        assert_eq!(*run_expr("7.5 % 2"), VNumb(1.5));
+        assert_eq!(*run_expr("(-9223372036854775807 - 1) % -1"), VInt(0));
+
+        let env = Environment::new();
+        assert_eq!(eval_in(&env, "1 % 0"), Err("Cannot take the remainder of 1 divided by 0".to_string()));
+        assert_eq!(eval_in(&env, "\"a\" % 2"), Err("Cannot take the remainder of String divided by Int".to_string()));
+    }
+
+    #[test]
+    fn conversions() {
+        let int = |v: LoxType| LoxValue::new(v).to_int().map(|v| (*v).clone());
+        let float = |v: LoxType| LoxValue::new(v).to_float().map(|v| (*v).clone());
+        assert_eq!(int(VNumb(-2.7)), Ok(VInt(-2)));
+        assert_eq!(int(VStr(String::from(" 42 "))), Ok(VInt(42)));
+        assert_eq!(int(VNumb(1e20)), Ok(VBigInt("100000000000000000000".parse().unwrap())));
+        assert_eq!(int(VNumb(f64::INFINITY)), Err("Cannot convert Float inf to Int".to_string()));
+        assert_eq!(int(VStr(String::from("18446744073709551616"))), Ok(VBigInt("18446744073709551616".parse().unwrap())));
+        assert_eq!(int(VStr(String::from("x"))), Err("Cannot convert String x to Int".to_string()));
+        assert_eq!(float(VInt(3)), Ok(VNumb(3.0)));
+        assert_eq!(float(VStr(String::from("2.5"))), Ok(VNumb(2.5)));
+        assert_eq!(float(VNil), Err("Cannot convert Nil nil to Float".to_string()));
+    }
+
     #[test]
     fn compare() {
         assert_eq!(*run_expr("2<3"), VBool(true));
@@ -250,12 +588,140 @@ mod tests {
 
     #[test]
     fn group() {
-        assert_eq!(*run_expr("2 + (3*4)"), VNumb(14.0));
+        assert_eq!(*run_expr("2 + (3*4)"), VInt(14));
+    }
+
+    #[test]
+    fn logical() {
+        assert_eq!(*run_expr("nil or \"default\""), VStr(String::from("default")));
+        assert_eq!(*run_expr("\"a\" or \"b\""), VStr(String::from("a")));
+        assert_eq!(*run_expr("1 and 2"), VInt(2));
+        assert_eq!(*run_expr("nil and 2"), VNil);
+        assert_eq!(*run_expr("false or nil"), VNil);
+        assert_eq!(*run_expr("true and false"), VBool(false));
+    }
+
+    #[test]
+    fn logical_short_circuit() {
+        let env = run_program("\
+var calls = 0;
+fun f() { calls = calls + 1; return calls; }
+var a = true or f();
+var b = false and f();
+var c = nil or f();
+var d = 1 and f();
+");
+
+        assert_eq!(*env.lookup("calls").unwrap(), VInt(2));
+        assert_eq!(*env.lookup("a").unwrap(), VBool(true));
+        assert_eq!(*env.lookup("b").unwrap(), VBool(false));
+        assert_eq!(*env.lookup("c").unwrap(), VInt(1));
+        assert_eq!(*env.lookup("d").unwrap(), VInt(2));
+    }
+
+    #[test]
+    fn anonymous_functions() {
+        let env = run_program("\
+fun counter() {
+    var count = 0;
+    return () => {
+        count = count + 1;
+        return count;
+    };
+}
+var next = counter();
+next();
+var a = next();
+var b = (fun (x, y) { return x * y; })(a, 3);
+var c = ((x) => x + a)(1);
+");
+
+        assert_eq!(*env.lookup("a").unwrap(), VInt(2));
+        assert_eq!(*env.lookup("b").unwrap(), VInt(6));
+        assert_eq!(*env.lookup("c").unwrap(), VInt(3));
+        assert!(env.lookup("count").is_err());
+    }
+
+    #[test]
+    fn default_and_rest_params() {
+        let env = run_program("\
+fun f(a, b = a * 10, ...rest) { return \"${a} ${b} ${rest}\"; }
+fun list(...items) { return items; }
+fun g(a, b = 2) {}
+var one = f(1);
+var two = f(1, 2);
+var many = f(1, 2, 3, 4);
+var spread = f(0, ...list(5, 6, 7));
+");
+
+        assert_eq!(env.lookup("one").unwrap().value_string(), "1 10 []");
+        assert_eq!(env.lookup("two").unwrap().value_string(), "1 2 []");
+        assert_eq!(env.lookup("many").unwrap().value_string(), "1 2 [3, 4]");
+        assert_eq!(env.lookup("spread").unwrap().value_string(), "0 5 [6, 7]");
+
+        assert_eq!(eval_in(&env, "f()"), Err("Function f requires at least 1 argument(s), got 0".to_string()));
+        assert_eq!(eval_in(&env, "f(...1)"), Err("Cannot spread Int into arguments".to_string()));
+        assert_eq!(eval_in(&env, "g(1, 2, 3)"), Err("Function g requires 1 to 2 argument(s), got 3".to_string()));
+    }
+
+    #[test]
+    fn named_args() {
+        let env = run_program("\
+fun draw(width, height, fill = \"#\") { return \"${width}x${height}${fill}\"; }
+var a = draw(height: 2, width: 1);
+var b = draw(1, fill: \"*\", height: 3);
+");
+
+        assert_eq!(env.lookup("a").unwrap().value_string(), "1x2#");
+        assert_eq!(env.lookup("b").unwrap().value_string(), "1x3*");
+
+        assert_eq!(
+            eval_in(&env, "draw(1, 2, depth: 3)"),
+            Err("Function draw has no parameter named 'depth' at line 1, column 12".to_string()),
+        );
+        assert_eq!(
+            eval_in(&env, "draw(1, width: 2)"),
+            Err("Argument 'width' is given more than once at line 1, column 9".to_string()),
+        );
+        assert_eq!(
+            eval_in(&env, "draw(fill: \"-\", width: 2)"),
+            Err("Function draw is missing argument 'height' at line 1, column 5".to_string()),
+        );
+    }
+
+    #[test]
+    fn tail_calls() {
+        // without tail calls reusing the frame this overflows a small stack
+        let depth = std::thread::Builder::new().stack_size(256 * 1024).spawn(|| {
+            let env = run_program("\
+fun count(n, total) {
+    if n == 0 {
+        return total;
+    }
+    return count(n - 1, total + 1);
+}
+var depth = count(1000000, 0);
+");
+            env.lookup("depth").unwrap().value_string()
+        }).unwrap().join().unwrap();
+        assert_eq!(depth, "1000000");
+    }
+
+    #[test]
+    fn interpolation() {
+        let env = Environment::new();
+        env.var("name", Some(LoxValue::new(VStr(String::from("Ann")))));
+        env.var("age", Some(LoxValue::new(VNumb(41.0))));
+        assert_eq!(
+            *eval_in(&env, "\"Hello ${name}, you are ${age + 1}${\"!\"}\"").unwrap(),
+            VStr(String::from("Hello Ann, you are 42.0!")),
+        );
+        assert_eq!(*run_expr("\"${nil} ${1 < 2}\""), VStr(String::from("nil true")));
     }
 
     #[test]
     fn unary() {
-        assert_eq!(*run_expr("-3 + 4"), VNumb(1.0));
+        assert_eq!(*run_expr("-3 + 4"), VInt(1));
         assert_eq!(*run_expr("!true"), VBool(false));
         assert_eq!(*run_expr("!123"), VBool(false));
     }
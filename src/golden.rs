@@ -0,0 +1,329 @@
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::compiler::Backend;
+use crate::hooks::{self, Hook};
+use crate::interpreter::{InterpretError, Interpreter};
+use crate::optimizer::Passes;
+use crate::parser::{parse_dialect, Dialect};
+use crate::source::{Source, SourceError};
+use crate::tokenizer::tokenize;
+
+
+const EXPECT: &str = "// expect: ";
+const EXPECT_RUNTIME_ERROR: &str = "// expect runtime error: ";
+
+// Deeply recursive test programs need more than the default 2 MiB.
+const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+
+/// An expected line of output or error message, with the line of the
+/// comment that declared it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expected {
+    pub line: usize,
+    pub text: String,
+}
+
+
+/// The annotations in a test file, in the format of the Crafting
+/// Interpreters test suite:
+///
+/// - `// expect: text` for each line the program prints,
+/// - `// expect runtime error: message` if it fails while running,
+/// - `// [line N] Error...` or `// Error...` (meaning the comment's own
+///   line) if it fails to compile. The text after `Error` has to appear
+///   in the error message.
+#[derive(Debug, Default, PartialEq)]
+pub struct Expectations {
+    pub output: Vec<Expected>,
+    pub runtime_error: Option<Expected>,
+    pub compile_errors: Vec<Expected>,
+}
+
+impl Expectations {
+    pub fn parse(content: &str) -> Expectations {
+        let mut expectations = Expectations::default();
+        for (idx, line) in content.lines().enumerate() {
+            let lineno = idx + 1;
+            if let Some(start) = line.find(EXPECT) {
+                expectations.output.push(Expected {
+                    line: lineno,
+                    text: line[start + EXPECT.len()..].to_string(),
+                });
+            } else if let Some(start) = line.find(EXPECT_RUNTIME_ERROR) {
+                expectations.runtime_error = Some(Expected {
+                    line: lineno,
+                    text: line[start + EXPECT_RUNTIME_ERROR.len()..].to_string(),
+                });
+            } else if let Some(error) = compile_error(line, lineno) {
+                expectations.compile_errors.push(error);
+            }
+        }
+        expectations
+    }
+}
+
+
+fn compile_error(line: &str, lineno: usize) -> Option<Expected> {
+    let comment = line[line.find("//")? + 2..].trim_start();
+    let (lineno, rest) = match comment.strip_prefix("[line ") {
+        Some(rest) => {
+            let (n, rest) = rest.split_once(']')?;
+            (n.trim().parse().ok()?, rest.trim_start())
+        },
+        None => (lineno, comment),
+    };
+    let text = rest.strip_prefix("Error")?;
+    // so comments like "// Errors are ..." aren't expectations
+    if !(text.is_empty() || text.starts_with(':') || text.starts_with(" at")) {
+        return None;
+    }
+    Some(Expected {
+        line: lineno,
+        text: text.trim_start_matches(':').trim().to_string(),
+    })
+}
+
+
+/// The result of running one test file: it passed if there are no
+/// failures.
+#[derive(Debug)]
+pub struct Outcome {
+    pub path: PathBuf,
+    pub failures: Vec<String>,
+}
+
+impl Outcome {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+
+struct Capture(Rc<RefCell<Vec<String>>>);
+
+impl Hook for Capture {
+    fn print(&mut self, text: &str) -> bool {
+        self.0.borrow_mut().extend(text.split('\n').map(|l| l.to_string()));
+        true
+    }
+}
+
+
+/// Runs `src` as `dialect`, optimized with `passes`, on `backend`,
+/// capturing what it prints, and checks it against the annotations in its
+/// content. Returns a description of each mismatch.
+pub fn check(src: &mut Source, dialect: Dialect, passes: Passes, backend: Backend) -> Vec<String> {
+    let expected = Expectations::parse(&src.content);
+    let mut failures = Vec::new();
+
+    let parse_as = Dialect::from_pragma(&src.content).unwrap_or(dialect);
+    let compiled = tokenize(src).map_err(|e| diagnostic(&e)).and_then(|tokens| {
+        parse_dialect(&tokens, parse_as).map(|_| ()).map_err(|e| diagnostic(&e))
+    });
+    if let Err((line, msg)) = compiled {
+        let matched = expected.compile_errors.iter()
+            .any(|e| e.line == line && msg.contains(&e.text));
+        if !matched {
+            failures.push(format!("unexpected compile error: [line {}] Error: {}", line, msg));
+        }
+        return failures;
+    }
+    for error in expected.compile_errors.iter() {
+        failures.push(format!("expected compile error on line {} but there was none", error.line));
+    }
+
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let installed = hooks::clear();
+    hooks::install(Box::new(Capture(output.clone())));
+    let result = Interpreter::new()
+        .with_dialect(dialect)
+        .with_passes(passes)
+        .with_backend(backend)
+        .interpret(src);
+    hooks::clear();
+    installed.into_iter().for_each(hooks::install);
+
+    let output = output.borrow();
+    for idx in 0..output.len().max(expected.output.len()) {
+        match (expected.output.get(idx), output.get(idx)) {
+            (Some(e), Some(actual)) if e.text == *actual => (),
+            (Some(e), Some(actual)) => failures.push(format!(
+                "line {}: expected output {:?}, got {:?}",
+                e.line,
+                e.text,
+                actual,
+            )),
+            (Some(e), None) => failures.push(format!(
+                "line {}: missing expected output {:?}",
+                e.line,
+                e.text,
+            )),
+            (None, Some(actual)) => failures.push(format!("unexpected output {:?}", actual)),
+            (None, None) => (),
+        }
+    }
+
+    match (result, &expected.runtime_error) {
+        (Err(InterpretError::Runtime(msg)), Some(e)) if msg.contains(&e.text) => (),
+        (Err(e), Some(expected)) => failures.push(format!(
+            "line {}: expected runtime error {:?}, got {:?}",
+            expected.line,
+            expected.text,
+            e.to_string(),
+        )),
+        (Ok(_), Some(expected)) => failures.push(format!(
+            "line {}: expected runtime error {:?} but the program succeeded",
+            expected.line,
+            expected.text,
+        )),
+        (Err(e), None) => failures.push(format!("unexpected error: {}", e)),
+        (Ok(_), None) => (),
+    }
+
+    failures
+}
+
+
+fn diagnostic<E: SourceError>(err: &E) -> (usize, String) {
+    let line = err.get_position().map(|p| p.lineno).unwrap_or(0);
+    (line, err.get_message().to_string())
+}
+
+
+pub fn run_file(path: &Path, dialect: Dialect, passes: Passes, backend: Backend) -> Outcome {
+    let failures = match Source::from_file(&path.to_string_lossy()) {
+        Ok(mut src) => check(&mut src, dialect, passes, backend),
+        Err(e) => vec![e],
+    };
+    Outcome {
+        path: path.to_path_buf(),
+        failures,
+    }
+}
+
+
+/// The `.lox` files in `paths`, searching directories recursively, sorted.
+pub fn collect(paths: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let entries = fs::read_dir(path)
+                .map_err(|e| format!("Failed to read directory '{}': {}", path.display(), e))?;
+            let children: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|p| p.is_dir() || p.extension().is_some_and(|ext| ext == "lox"))
+                .collect();
+            files.extend(collect(&children)?);
+        } else if path.exists() {
+            files.push(path.clone());
+        } else {
+            return Err(format!("No such file or directory '{}'", path.display()));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+
+/// Runs `files` on `jobs` threads, returning outcomes in the order of
+/// `files`.
+pub fn run_all(
+    files: &[PathBuf],
+    jobs: usize,
+    dialect: Dialect,
+    passes: Passes,
+    backend: Backend,
+) -> Vec<Outcome> {
+    let next = AtomicUsize::new(0);
+    let outcomes = Mutex::new(Vec::new());
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, files.len().max(1)) {
+            thread::Builder::new()
+                .stack_size(STACK_SIZE)
+                .spawn_scoped(scope, || loop {
+                    let idx = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(path) = files.get(idx) else { break };
+                    let outcome = run_file(path, dialect, passes, backend);
+                    outcomes.lock().unwrap().push((idx, outcome));
+                })
+                .expect("failed to spawn test thread");
+        }
+    });
+
+    let mut outcomes = outcomes.into_inner().unwrap();
+    outcomes.sort_by_key(|(idx, _)| *idx);
+    outcomes.into_iter().map(|(_, outcome)| outcome).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn check_str(code: &str) -> Vec<String> {
+        check(&mut Source::from_string(code.to_string()), Dialect::Bwl, Passes::default(), Backend::Tree)
+    }
+
+    #[test]
+    fn test_parse_expectations() {
+        let expectations = Expectations::parse("\
+print 1; // expect: 1
+print -\"a\"; // expect runtime error: Cannot negate
+var = 1; // Error: Expected variable name
+// [line 7] Error at ';': Expect expression.
+// Errors here are reported once.
+// ErrorKind is not an expectation either
+print 2; // Error
+");
+        assert_eq!(expectations, Expectations {
+            output: vec![Expected { line: 1, text: "1".to_string() }],
+            runtime_error: Some(Expected { line: 2, text: "Cannot negate".to_string() }),
+            compile_errors: vec![
+                Expected { line: 3, text: "Expected variable name".to_string() },
+                Expected { line: 7, text: "at ';': Expect expression.".to_string() },
+                Expected { line: 7, text: "".to_string() },
+            ],
+        });
+    }
+
+    #[test]
+    fn test_output() {
+        assert_eq!(check_str("print 1; // expect: 1\nprint \"a\"; // expect: a\n"), Vec::<String>::new());
+        assert_eq!(check_str("print 1; // expect: 2\nprint 3;\n"), vec![
+            "line 1: expected output \"2\", got \"1\"".to_string(),
+            "unexpected output \"3\"".to_string(),
+        ]);
+        assert_eq!(check_str("// expect: 1\n"), vec![
+            "line 1: missing expected output \"1\"".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            check_str("print 1; // expect: 1\nprint -\"a\"; // expect runtime error: Cannot negate String\n"),
+            Vec::<String>::new(),
+        );
+        assert_eq!(check_str("print -\"a\";\n"), vec![
+            "unexpected error: Cannot negate String".to_string(),
+        ]);
+        assert_eq!(check_str("print 1 + ; // [line 1] Error: could not parse\n"), Vec::<String>::new());
+        assert_eq!(check_str("print 1 + ;\n"), vec![
+            "unexpected compile error: [line 1] Error: could not parse token type 'SemiColon'".to_string(),
+        ]);
+        assert_eq!(check_str("print 1; // expect runtime error: boom\n"), vec![
+            "unexpected output \"1\"".to_string(),
+            "line 1: expected runtime error \"boom\" but the program succeeded".to_string(),
+        ]);
+    }
+}
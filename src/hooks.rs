@@ -0,0 +1,165 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::ast::{Stmt, AST};
+use crate::environment::Environment;
+use crate::source::{FilePosition, Source};
+use crate::value::LoxValue;
+
+
+/// Observer the evaluator notifies as it runs a program.
+///
+/// Every method has a no-op default so implementors only need to
+/// override the events they care about. Returning an error from
+/// `before_stmt` or `enter_call` aborts evaluation with that error.
+pub trait Hook {
+    /// Called with each source and its AST before it is run.
+    fn loaded(&mut self, _src: &Source, _ast: &AST) {}
+
+    fn before_stmt(&mut self, _stmt: &Stmt, _env: &Rc<Environment>) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn after_stmt(&mut self, _stmt: &Stmt) {}
+
+    fn enter_call(&mut self, _func: &LoxValue, _args: &[LoxValue]) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn exit_call(&mut self, _func: &LoxValue, _result: Option<&LoxValue>) {}
+
+    /// Called when the `if`, `while`, `and` or `or` at `pos` takes one of
+    /// its two branches: the then branch, loop body or right operand is
+    /// branch 0, the other outcome branch 1.
+    fn branch(&mut self, _pos: &FilePosition, _branch: usize) {}
+
+    /// Called when a variable is read, with the value read.
+    fn read(&mut self, _name: &str, _value: &LoxValue) {}
+
+    /// Called when a variable is declared or assigned, with the new value.
+    fn write(&mut self, _name: &str, _value: Option<&LoxValue>) {}
+
+    /// Called with the text of each `print` statement. Returning true
+    /// consumes the output instead of it being written to stdout.
+    fn print(&mut self, _text: &str) -> bool {
+        false
+    }
+}
+
+
+thread_local! {
+    static HOOKS: RefCell<Vec<Box<dyn Hook>>> = RefCell::new(Vec::new());
+}
+
+
+/// Registers a hook for evaluation on the current thread.
+pub fn install(hook: Box<dyn Hook>) {
+    HOOKS.with(|hooks| hooks.borrow_mut().push(hook));
+}
+
+
+/// Whether any hooks are registered on the current thread, for callers
+/// that only need to keep track of something for hooks.
+pub fn active() -> bool {
+    HOOKS.with(|hooks| !hooks.borrow().is_empty())
+}
+
+
+/// Removes and returns all hooks registered on the current thread.
+pub fn clear() -> Vec<Box<dyn Hook>> {
+    HOOKS.with(|hooks| hooks.take())
+}
+
+
+// The hooks are moved out while they run so a hook can itself evaluate
+// code (e.g. a debugger printing an expression) without re-entering. Most
+// programs run without hooks, so that case returns before moving anything.
+fn dispatch(mut f: impl FnMut(&mut dyn Hook) -> Result<(), String>) -> Result<(), String> {
+    if !active() {
+        return Ok(());
+    }
+    let mut active = HOOKS.with(|hooks| hooks.take());
+
+    let mut result = Ok(());
+    for hook in active.iter_mut() {
+        if let Err(e) = f(hook.as_mut()) {
+            result = Err(e);
+            break;
+        }
+    }
+
+    HOOKS.with(|hooks| {
+        let mut hooks = hooks.borrow_mut();
+        active.append(&mut hooks);
+        *hooks = active;
+    });
+    result
+}
+
+
+pub fn loaded(src: &Source, ast: &AST) {
+    let _ = dispatch(|hook| {
+        hook.loaded(src, ast);
+        Ok(())
+    });
+}
+
+
+pub fn before_stmt(stmt: &Stmt, env: &Rc<Environment>) -> Result<(), String> {
+    dispatch(|hook| hook.before_stmt(stmt, env))
+}
+
+
+pub fn after_stmt(stmt: &Stmt) {
+    let _ = dispatch(|hook| {
+        hook.after_stmt(stmt);
+        Ok(())
+    });
+}
+
+
+pub fn enter_call(func: &LoxValue, args: &[LoxValue]) -> Result<(), String> {
+    dispatch(|hook| hook.enter_call(func, args))
+}
+
+
+pub fn exit_call(func: &LoxValue, result: Option<&LoxValue>) {
+    let _ = dispatch(|hook| {
+        hook.exit_call(func, result);
+        Ok(())
+    });
+}
+
+
+pub fn branch(pos: &FilePosition, branch: usize) {
+    let _ = dispatch(|hook| {
+        hook.branch(pos, branch);
+        Ok(())
+    });
+}
+
+
+pub fn read(name: &str, value: &LoxValue) {
+    let _ = dispatch(|hook| {
+        hook.read(name, value);
+        Ok(())
+    });
+}
+
+
+pub fn write(name: &str, value: Option<&LoxValue>) {
+    let _ = dispatch(|hook| {
+        hook.write(name, value);
+        Ok(())
+    });
+}
+
+
+pub fn print(text: &str) -> bool {
+    let mut handled = false;
+    let _ = dispatch(|hook| {
+        handled |= hook.print(text);
+        Ok(())
+    });
+    handled
+}
@@ -1,42 +1,166 @@
+use std::fmt;
 use std::rc::Rc;
 
+use crate::compiler::{Backend, Compiler};
 use crate::evaluator::interpret;
+use crate::optimizer::{optimize, Passes};
+use crate::value::{LoxType, LoxValue, Native};
 
 use super::source::Source;
 use super::environment::Environment;
-use super::parser::parse;
+use super::hooks;
+use super::parser::{parse_dialect, Dialect};
 use super::tokenizer::tokenize;
 
+/// Errors from `Interpreter::interpret`, split so callers can tell a
+/// program that never ran from one that failed part way through.
+#[derive(Debug, PartialEq)]
+pub enum InterpretError {
+    Compile(String),
+    Runtime(String),
+}
+
+impl InterpretError {
+    /// The exit status clox uses for the error: 65 (`EX_DATAERR`) for
+    /// compile errors, 70 (`EX_SOFTWARE`) for runtime errors.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            InterpretError::Compile(_) => 65,
+            InterpretError::Runtime(_) => 70,
+        }
+    }
+}
+
+impl fmt::Display for InterpretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InterpretError::Compile(msg) | InterpretError::Runtime(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+
 pub struct Interpreter {
     env: Rc<Environment>,
+    dialect: Dialect,
+    passes: Passes,
+    backend: Backend,
+    compiler: Compiler,
+    args: Option<Vec<String>>,
 }
 
 impl<'a> Interpreter {
     pub fn new() -> Interpreter {
-        Interpreter{
+        let mut interpreter = Interpreter{
             env: Environment::new(),
+            dialect: Dialect::default(),
+            passes: Passes::default(),
+            backend: Backend::default(),
+            compiler: Compiler::new(),
+            args: None,
+        };
+        interpreter.define_native("int", 1, |vals| vals[0].to_int());
+        interpreter.define_native("float", 1, |vals| vals[0].to_float());
+        interpreter
+    }
+
+    /// Parses sources as `dialect` unless they name one in a pragma.
+    pub fn with_dialect(mut self, dialect: Dialect) -> Interpreter {
+        self.dialect = dialect;
+        self
+    }
+
+    pub fn dialect(&self) -> Dialect {
+        self.dialect
+    }
+
+    /// Optimizes sources with `passes` before running them.
+    pub fn with_passes(mut self, passes: Passes) -> Interpreter {
+        self.passes = passes;
+        self
+    }
+
+    pub fn passes(&self) -> Passes {
+        self.passes
+    }
+
+    /// Runs programs with `backend`.
+    pub fn with_backend(mut self, backend: Backend) -> Interpreter {
+        self.backend = backend;
+        self
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// The global environment.
+    pub fn env(&self) -> &Rc<Environment> {
+        &self.env
+    }
+
+    /// Defines a global function implemented in Rust.
+    pub fn define_native<F>(&mut self, name: &str, arity: usize, func: F)
+    where
+        F: Fn(&[LoxValue]) -> Result<LoxValue, String> + 'static,
+    {
+        let native = LoxValue::new(LoxType::VNative(Native::new(name, arity, func)));
+        self.env.var(name, Some(native));
+    }
+
+    /// Exposes script arguments to Lox as `argc` and `arg(i)`.
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.args = Some(args.clone());
+        self.env.var("argc", Some(LoxValue::new(LoxType::VInt(args.len() as i64))));
+        self.define_native("arg", 1, move |vals| match &*vals[0] {
+            LoxType::VInt(i) if *i >= 0 && (*i as usize) < args.len() => {
+                Ok(LoxValue::new(LoxType::VStr(args[*i as usize].clone())))
+            },
+            _ => Err(format!(
+                "IndexError: arg({}) out of range, {} argument(s) given",
+                vals[0].value_string(),
+                args.len(),
+            )),
+        });
+    }
+
+    /// Forgets all globals, but keeps the settings and script arguments.
+    pub fn reset(&mut self) {
+        let args = self.args.take();
+        *self = Interpreter::new()
+            .with_dialect(self.dialect)
+            .with_passes(self.passes)
+            .with_backend(self.backend);
+        if let Some(args) = args {
+            self.set_args(args);
         }
     }
 
-    pub fn interpret<'b>(&mut self, src: &'b mut Source) -> Result<Option<String>, String> {
+    pub fn interpret(&mut self, src: &mut Source) -> Result<Option<String>, InterpretError> {
         let tokens = match tokenize(src) {
             Ok(v) => v,
             Err(e) => {
-                return Err(src.format_error(&e));
+                return Err(InterpretError::Compile(src.format_error(&e)));
             },
         };
 
-        let ast = match parse(&tokens) {
+        let dialect = Dialect::from_pragma(&src.content).unwrap_or(self.dialect);
+        let mut ast = match parse_dialect(&tokens, dialect) {
             Ok(v) => v,
             Err(e) => {
-                return Err(src.format_error(&e));
+                return Err(InterpretError::Compile(src.format_error(&e)));
             },
         };
+        if self.passes.any() {
+            optimize(&mut ast, &self.passes);
+        }
+        hooks::loaded(src, &ast);
 
-        Ok(match interpret(&ast.top, &self.env)? {
-            Some(v) => Some(v.value_string()),
-            None => None,
-        })
+        let result = match self.backend {
+            Backend::Tree => interpret(&ast.top, &self.env),
+            Backend::Closures => self.compiler.run(&ast.top, &self.env),
+        };
+        Ok(result.map_err(InterpretError::Runtime)?.map(|v| v.value_string()))
 
         // TODO: only do this in repl
         //if let Ok(result) = self.interpret_expression(src, &tokens) {
@@ -96,4 +220,26 @@ mod tests {
             &mut Source::from_string("string".to_string()),
         );
     }
+
+    #[test]
+    fn test_error_kinds() {
+        let mut interpreter = Interpreter::new();
+        let compile = interpreter.interpret(&mut Source::from_string("var = 1;".to_string()));
+        assert!(matches!(compile, Err(InterpretError::Compile(_))));
+        assert_eq!(compile.unwrap_err().exit_code(), 65);
+
+        let runtime = interpreter.interpret(&mut Source::from_string("print -\"a\";".to_string()));
+        assert!(matches!(runtime, Err(InterpretError::Runtime(_))));
+        assert_eq!(runtime.unwrap_err().exit_code(), 70);
+    }
+
+    #[test]
+    fn test_args() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_args(vec!["a".to_string(), "b".to_string()]);
+        let mut run = |code: &str| interpreter.interpret(&mut Source::from_string(code.to_string()));
+        assert_eq!(run("return argc;"), Ok(Some("2".to_string())));
+        assert_eq!(run("return arg(1) + arg(0);"), Ok(Some("ba".to_string())));
+        assert!(matches!(run("arg(2);"), Err(InterpretError::Runtime(_))));
+    }
 }
@@ -4,5 +4,16 @@ pub mod tokenizer;
 pub mod ast;
 pub mod parser;
 pub mod evaluator;
+pub mod optimizer;
+pub mod compiler;
 pub mod interpreter;
+pub mod repl;
 pub mod value;
+pub mod bigint;
+pub mod hooks;
+pub mod debugger;
+pub mod dap;
+pub mod profiler;
+pub mod coverage;
+pub mod tracer;
+pub mod golden;
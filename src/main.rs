@@ -1,105 +1,529 @@
-use clap::Parser;
+use clap::{ArgAction, Args, Parser, Subcommand};
+use std::fs;
 use std::io;
-use std::io::IsTerminal;
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
+use std::process;
+use std::thread;
+use bagelwithlox::coverage::{Coverage, CoverageRecorder};
+use bagelwithlox::dap;
+use bagelwithlox::golden;
+use bagelwithlox::debugger::{self, Console, Debugger};
+use bagelwithlox::hooks;
+use bagelwithlox::ast::AST;
+use bagelwithlox::compiler::Backend;
+use bagelwithlox::optimizer::{optimize, Passes};
+use bagelwithlox::parser::{parse_dialect, Dialect};
+use bagelwithlox::profiler::{Profile, Profiler};
+use bagelwithlox::repl;
 use bagelwithlox::source::Source;
-use bagelwithlox::interpreter::Interpreter;
-use rustyline::error::ReadlineError;
-use rustyline::{DefaultEditor, Result as RLResult};
+use bagelwithlox::tokenizer::tokenize;
+use bagelwithlox::tracer::{TraceFormat, Tracer};
+use bagelwithlox::interpreter::{InterpretError, Interpreter};
+
+
+// Exit statuses from sysexits.h, as used by clox.
+const EX_OK: i32 = 0;
+const EX_DATAERR: i32 = 65;
+const EX_IOERR: i32 = 74;
+
+const EX_TESTFAIL: i32 = 1;
+
 
 #[derive(Parser)]
 #[command(
     version,
     about,
+    args_conflicts_with_subcommands = true,
 )]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Only print program output and errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Report what bwl is doing on stderr; repeat to also dump the source
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+    /// Syntax to parse scripts as, unless they start with a
+    /// `// dialect: ...` comment
+    #[arg(long, global = true, value_enum, default_value_t = DialectArg::Bwl)]
+    dialect: DialectArg,
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+
+#[derive(Args)]
+struct RunArgs {
+    /// Run CMD instead of a file
     #[arg(short)]
     cmd: Option<String>,
+    /// Print a per-function timing summary to stderr and write folded
+    /// stacks for flamegraph tools to FOLDED
+    #[arg(
+        long,
+        value_name = "FOLDED",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "profile.folded",
+    )]
+    profile: Option<String>,
+    /// Write statement and branch coverage in lcov format to LCOV, and an
+    /// annotated source listing to LCOV.txt
+    #[arg(long, value_name = "LCOV")]
+    coverage: Option<String>,
+    /// Log executed statements, variable reads and writes, and function
+    /// calls to TRACE, or to stderr if no file is given
+    #[arg(
+        long,
+        value_name = "TRACE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "-",
+    )]
+    trace: Option<String>,
+    /// Format of the --trace log
+    #[arg(long, value_enum, default_value_t = TraceFormatArg::Text)]
+    trace_format: TraceFormatArg,
+    /// Optimize the script before running it with the comma-separated
+    /// PASSES: fold, branches, empty and blocks, or all of them
+    #[arg(
+        long,
+        value_name = "PASSES",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "all",
+        value_parser = Passes::parse,
+    )]
+    optimize: Option<Passes>,
+    /// Print the syntax tree to stderr before running, as parsed or after
+    /// the --optimize passes, which default to all of them here
+    #[arg(
+        long,
+        value_enum,
+        value_name = "STAGE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "parsed",
+    )]
+    dump_ast: Option<DumpAstArg>,
+    /// Run the script by walking its syntax tree, or by compiling it into
+    /// closures first
+    #[arg(long, value_name = "BACKEND", default_value = "tree", value_parser = Backend::parse)]
+    backend: Backend,
+    /// The script to run, read from stdin if not given
     file: Option<String>,
+    /// Arguments for the script, available as `argc` and `arg(i)`
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    args: Vec<String>,
+}
+
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum DialectArg {
+    /// Braces around bodies, no parentheses around conditions
+    Bwl,
+    /// The syntax from Crafting Interpreters
+    Classic,
+}
+
+impl From<DialectArg> for Dialect {
+    fn from(arg: DialectArg) -> Dialect {
+        match arg {
+            DialectArg::Bwl => Dialect::Bwl,
+            DialectArg::Classic => Dialect::Classic,
+        }
+    }
+}
+
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum DumpAstArg {
+    Parsed,
+    Optimized,
+}
+
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum TraceFormatArg {
+    Text,
+    Json,
+}
+
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run a script; the default when no subcommand is given
+    Run(RunArgs),
+    /// Start the interactive prompt
+    Repl,
+    /// Tokenize and parse a file without running it
+    Check {
+        file: String,
+    },
+    /// Print the tokens of a file with their positions
+    Tokens {
+        file: String,
+    },
+    /// Print the syntax tree of a file
+    Ast {
+        file: String,
+    },
+    /// Run .lox files and check their output against `// expect:` comments
+    Test {
+        /// Files, or directories to search for .lox files
+        #[arg(default_value = ".")]
+        paths: Vec<PathBuf>,
+        /// Number of files to run at once; defaults to the number of CPUs
+        #[arg(short, long)]
+        jobs: Option<usize>,
+        /// Optimize the files with the comma-separated PASSES, or all of
+        /// them, before running them
+        #[arg(
+            long,
+            value_name = "PASSES",
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "all",
+            value_parser = Passes::parse,
+        )]
+        optimize: Option<Passes>,
+        /// Run the files on BACKEND: tree or closures
+        #[arg(long, value_name = "BACKEND", default_value = "tree", value_parser = Backend::parse)]
+        backend: Backend,
+    },
+    /// Run a file under the interactive step debugger
+    Debug {
+        file: String,
+    },
+    /// Serve the Debug Adapter Protocol on stdin/stdout
+    Dap,
+}
+
+
+/// How much bwl says about itself on stderr: -1 with `--quiet`, otherwise
+/// the number of `-v` flags.
+#[derive(Clone, Copy)]
+struct Verbosity(i8);
+
+impl Verbosity {
+    fn info(&self, msg: &str) {
+        if self.0 >= 1 {
+            eprintln!("{}", msg);
+        }
+    }
+
+    fn notice(&self, msg: &str) {
+        if self.0 >= 0 {
+            eprintln!("{}", msg);
+        }
+    }
 }
 
-impl Cli {
-    fn get_source(&self) -> Option<Result<Source, String>> {
+
+impl RunArgs {
+    fn get_source(&self, verbosity: Verbosity) -> Result<Source, String> {
 
         if let Some(cmd) = &self.cmd {
-            eprintln!("Reading from command option");
-            return Some(Ok(Source::from_string(cmd.to_string())));
+            verbosity.info("Reading from command option");
+            return Ok(Source::from_string(cmd.to_string()));
         }
 
         if let Some(path) = &self.file {
-            eprintln!("Reading from file arg '{}'", &path);
-            return Some(Source::from_file(&path));
+            verbosity.info(&format!("Reading from file arg '{}'", &path));
+            return Source::from_file(path);
         }
 
-        if io::stdin().is_terminal() {
-            return None;
+        verbosity.info("Reading from stdin");
+        match io::read_to_string(io::stdin()) {
+            Ok(string) => Ok(Source::from_string(string)),
+            Err(e) => Err(format!("Failed to read stdin: {}", e)),
         }
+    }
 
-        eprintln!("Reading from stdin");
-        let stdin = match io::read_to_string(io::stdin()) {
-            Ok(string) => string,
-            Err(e) => return Some(Err(format!("Failed to read stdin: {}", e))),
-        };
+    /// With `-c` the first positional is a script argument, not a file.
+    fn script_args(&self) -> Vec<String> {
+        match &self.cmd {
+            Some(_) => self.file.iter().chain(self.args.iter()).cloned().collect(),
+            None => self.args.clone(),
+        }
+    }
 
-        return Some(Ok(Source::from_string(stdin)));
+    fn is_empty(&self) -> bool {
+        self.cmd.is_none() && self.file.is_none()
     }
 }
 
 
-fn repl(interpreter: &mut Interpreter) -> RLResult<()> {
-    eprintln!("Running the repl!");
-    let mut rl = DefaultEditor::new()?;
+fn load(path: &str) -> Result<Source, i32> {
+    Source::from_file(path).map_err(|e| {
+        eprintln!("ERROR: {}", e);
+        EX_IOERR
+    })
+}
 
-    loop {
-        let readline = rl.readline("bwl >");
-        match readline {
-            Ok(line) => {
-                if line.trim() == "" { continue; }
-                rl.add_history_entry(line.as_str())?;
-                match interpreter.interpret(
-                    &mut Source::from_string(line.to_string()),
-                ) {
-                    Ok(Some(result)) => println!("{}", result),
-                    Ok(None) => (),
-                    Err(e) => eprintln!("{}", e),
-                }
-            },
-            Err(ReadlineError::Interrupted) => {
-                println!("CTRL-C");
-                break
-            },
-            Err(ReadlineError::Eof) => {
-                println!("CTRL-D");
-                break
+
+fn run(run: &RunArgs, verbosity: Verbosity, dialect: Dialect) -> i32 {
+    let mut src = match run.get_source(verbosity) {
+        Ok(src) => src,
+        Err(e) => {
+            eprintln!("ERROR: {}", e);
+            return EX_IOERR;
+        },
+    };
+    if verbosity.0 >= 2 {
+        eprintln!("Got the following source content:\n'''\n{}\n'''", &src.content);
+    }
+
+    let profile = Profile::new();
+    if run.profile.is_some() {
+        hooks::install(Box::new(Profiler::new(&profile)));
+    }
+    let coverage = Coverage::new();
+    if run.coverage.is_some() {
+        hooks::install(Box::new(CoverageRecorder::new(&coverage)));
+    }
+    if let Some(path) = &run.trace {
+        let format = match run.trace_format {
+            TraceFormatArg::Text => TraceFormat::Text,
+            TraceFormatArg::Json => TraceFormat::JsonLines,
+        };
+        let out: Box<dyn io::Write> = match path.as_str() {
+            "-" => Box::new(io::stderr()),
+            path => match fs::File::create(path) {
+                Ok(file) => Box::new(io::BufWriter::new(file)),
+                Err(e) => {
+                    eprintln!("ERROR: failed to create '{}': {}", path, e);
+                    return EX_IOERR;
+                },
             },
-            Err(err) => {
-                println!("Error: {:?}", err);
-                break
+        };
+        hooks::install(Box::new(Tracer::new(out, format)));
+    }
+
+    let passes = match (run.optimize, run.dump_ast) {
+        (Some(passes), _) => passes,
+        // the optimized tree is only worth seeing after some passes
+        (None, Some(DumpAstArg::Optimized)) => Passes::all(),
+        (None, _) => Passes::default(),
+    };
+    if let Some(stage) = run.dump_ast {
+        // errors are reported when the script is run
+        if let Ok(mut ast) = parse_source(&src, dialect) {
+            if let DumpAstArg::Optimized = stage {
+                optimize(&mut ast, &passes);
+            }
+            eprint!("{}", ast);
+        }
+    }
+
+    let mut interpreter = Interpreter::new()
+        .with_dialect(dialect)
+        .with_passes(passes)
+        .with_backend(run.backend);
+    interpreter.set_args(run.script_args());
+    let status = match interpreter.interpret(&mut src) {
+        Ok(_) => EX_OK,
+        Err(e) => {
+            eprintln!("ERROR: {}", e);
+            e.exit_code()
+        },
+    };
+
+    hooks::clear();
+
+    if let Some(path) = &run.coverage {
+        let coverage = coverage.borrow();
+        let listing = format!("{}.txt", path);
+        for (path, content) in [(path, coverage.lcov()), (&listing, coverage.annotated())] {
+            if let Err(e) = fs::write(path, content) {
+                eprintln!("ERROR: failed to write '{}': {}", path, e);
             }
         }
     }
 
-    Ok(())
+    if let Some(path) = &run.profile {
+        let mut profile = profile.borrow_mut();
+        profile.finish();
+        eprint!("{}", profile.summary());
+        if let Err(e) = fs::write(path, profile.folded()) {
+            eprintln!("ERROR: failed to write '{}': {}", path, e);
+        }
+    }
+
+    status
 }
 
 
-fn main() {
-    let cli = Cli::parse();
-    let mut interpreter =  Interpreter::new();
-
-    if let Some(src) = cli.get_source() {
-        match src {
-            Ok(mut src) => {
-                eprintln!("Got the following source content:\n'''\n{}\n'''", &src.content);
-                if let Err(e) = interpreter.interpret(&mut src) {
-                    eprintln!("ERROR: {}", e)
-                }
-            },
-            Err(e) => eprintln!("ERROR: {}", e),
+fn check(path: &str, verbosity: Verbosity, dialect: Dialect) -> i32 {
+    let src = match load(path) {
+        Ok(src) => src,
+        Err(status) => return status,
+    };
+    let result = tokenize(&src)
+        .map_err(|e| src.format_error(&e))
+        .and_then(|tokens| {
+            let dialect = Dialect::from_pragma(&src.content).unwrap_or(dialect);
+            parse_dialect(&tokens, dialect).map_err(|e| src.format_error(&e))
+        });
+    match result {
+        Ok(_) => {
+            verbosity.info(&format!("{}: ok", path));
+            EX_OK
+        },
+        Err(e) => {
+            eprintln!("ERROR: {}", e);
+            EX_DATAERR
+        },
+    }
+}
+
+
+fn tokens(path: &str) -> i32 {
+    let src = match load(path) {
+        Ok(src) => src,
+        Err(status) => return status,
+    };
+    let tokens = match tokenize(&src) {
+        Ok(tokens) => tokens,
+        Err(e) => {
+            eprintln!("ERROR: {}", src.format_error(&e));
+            return EX_DATAERR;
+        },
+    };
+    for token in tokens.iter() {
+        println!("{}", token);
+    }
+    EX_OK
+}
+
+
+// Parses `src` as `dialect` unless it names one in a pragma.
+fn parse_source(src: &Source, dialect: Dialect) -> Result<AST, String> {
+    let tokens = tokenize(src).map_err(|e| src.format_error(&e))?;
+    let dialect = Dialect::from_pragma(&src.content).unwrap_or(dialect);
+    parse_dialect(&tokens, dialect).map_err(|e| src.format_error(&e))
+}
+
+
+fn ast(path: &str, dialect: Dialect) -> i32 {
+    let src = match load(path) {
+        Ok(src) => src,
+        Err(status) => return status,
+    };
+    match parse_source(&src, dialect) {
+        Ok(ast) => {
+            print!("{}", ast);
+            EX_OK
+        },
+        Err(e) => {
+            eprintln!("ERROR: {}", e);
+            EX_DATAERR
+        },
+    }
+}
+
+
+fn test(
+    paths: &[PathBuf],
+    jobs: Option<usize>,
+    passes: Passes,
+    backend: Backend,
+    verbosity: Verbosity,
+    dialect: Dialect,
+) -> i32 {
+    let files = match golden::collect(paths) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("ERROR: {}", e);
+            return EX_IOERR;
+        },
+    };
+    let jobs = jobs.unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+
+    let outcomes = golden::run_all(&files, jobs, dialect, passes, backend);
+    let failed = outcomes.iter().filter(|o| !o.passed()).count();
+    for outcome in outcomes.iter() {
+        if outcome.passed() {
+            if verbosity.0 >= 0 {
+                println!("PASS {}", outcome.path.display());
+            }
+            continue;
         }
-    } else {
-        match repl(&mut interpreter) {
-            Err(e) => eprintln!("ERROR: {}", e),
-            _ => (),
+        println!("FAIL {}", outcome.path.display());
+        for failure in outcome.failures.iter() {
+            println!("    {}", failure);
         }
     }
+    println!("{} passed, {} failed", outcomes.len() - failed, failed);
+
+    if failed > 0 { EX_TESTFAIL } else { EX_OK }
+}
+
+
+fn start_repl(run: &RunArgs, verbosity: Verbosity, dialect: Dialect) -> i32 {
+    verbosity.notice("Running the repl!");
+    let interpreter = Interpreter::new()
+        .with_dialect(dialect)
+        .with_passes(run.optimize.unwrap_or_default())
+        .with_backend(run.backend);
+    match repl::run(&mut repl::Session::new(interpreter)) {
+        Err(e) => {
+            eprintln!("ERROR: {}", e);
+            EX_IOERR
+        },
+        _ => EX_OK,
+    }
+}
+
+
+fn debug(path: &str, verbosity: Verbosity, dialect: Dialect) -> i32 {
+    let mut src = match load(path) {
+        Ok(src) => src,
+        Err(status) => return status,
+    };
+    verbosity.notice(&format!("Debugging '{}', type 'help' for a list of commands", path));
+
+    let console = Console::new(&src, io::stdin().lock(), io::stdout());
+    hooks::install(Box::new(Debugger::new(console)));
+    let result = Interpreter::new().with_dialect(dialect).interpret(&mut src);
+    hooks::clear();
+
+    match result {
+        Err(InterpretError::Runtime(e)) if e == debugger::QUIT => EX_OK,
+        Err(e) => {
+            eprintln!("ERROR: {}", e);
+            e.exit_code()
+        },
+        Ok(_) => EX_OK,
+    }
+}
+
+
+fn main() {
+    let cli = Cli::parse();
+    let verbosity = Verbosity(if cli.quiet { -1 } else { cli.verbose as i8 });
+    let dialect = Dialect::from(cli.dialect);
+
+    let status = match &cli.command {
+        Some(Command::Run(args)) => run(args, verbosity, dialect),
+        Some(Command::Repl) => start_repl(&cli.run, verbosity, dialect),
+        Some(Command::Check { file }) => check(file, verbosity, dialect),
+        Some(Command::Tokens { file }) => tokens(file),
+        Some(Command::Ast { file }) => ast(file, dialect),
+        Some(Command::Test { paths, jobs, optimize, backend }) => {
+            test(paths, *jobs, optimize.unwrap_or_default(), *backend, verbosity, dialect)
+        },
+        Some(Command::Debug { file }) => debug(file, verbosity, dialect),
+        Some(Command::Dap) => {
+            dap::serve(io::BufReader::new(io::stdin()), io::stdout());
+            EX_OK
+        },
+        None if cli.run.is_empty() && io::stdin().is_terminal() => start_repl(&cli.run, verbosity, dialect),
+        None => run(&cli.run, verbosity, dialect),
+    };
+
+    let _ = io::stdout().flush();
+    process::exit(status);
 }
@@ -0,0 +1,319 @@
+use std::rc::Rc;
+
+use crate::ast::{Expr, Interpretable, Interpretables, Operator, Param, Stmt, StmtKind, AST};
+use crate::environment::Environment;
+use crate::evaluator::eval;
+use crate::value::{LoxType, LoxValue};
+
+
+// The longest string folding may make by repeating one, so that folding a
+// repetition that never runs can't take more memory than running it would.
+const MAX_FOLDED_LEN: usize = 1024;
+
+
+/// The rewrites `optimize` makes, each of which can be turned on alone.
+/// None of them change what a program prints or the errors it fails
+/// with, though hooks see fewer statements and branches.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Passes {
+    /// Evaluate operators whose operands are literals, unless that fails.
+    pub fold: bool,
+    /// Replace `if` statements with literal conditions by the branch taken.
+    pub branches: bool,
+    /// Remove empty statements from blocks.
+    pub empty: bool,
+    /// Merge blocks that declare nothing into the enclosing block.
+    pub blocks: bool,
+}
+
+impl Passes {
+    pub const NAMES: [&'static str; 4] = ["fold", "branches", "empty", "blocks"];
+
+    pub fn all() -> Passes {
+        Passes { fold: true, branches: true, empty: true, blocks: true }
+    }
+
+    pub fn any(&self) -> bool {
+        self.fold || self.branches || self.empty || self.blocks
+    }
+
+    /// Parses a comma-separated list of the `NAMES` of passes, or `all`.
+    pub fn parse(names: &str) -> Result<Passes, String> {
+        let mut passes = Passes::default();
+        for name in names.split(',').map(str::trim) {
+            match name {
+                "all" => passes = Passes::all(),
+                "fold" => passes.fold = true,
+                "branches" => passes.branches = true,
+                "empty" => passes.empty = true,
+                "blocks" => passes.blocks = true,
+                _ => return Err(format!(
+                    "unknown pass '{}', expected 'all' or some of {}",
+                    name,
+                    Passes::NAMES.join(", "),
+                )),
+            }
+        }
+        Ok(passes)
+    }
+}
+
+
+pub fn optimize(ast: &mut AST, passes: &Passes) {
+    let mut top = Interpretables::new();
+    for interpretable in ast.top.drain(..) {
+        match interpretable {
+            Interpretable::IStmt(stmt) => {
+                top.extend(stmts(vec![stmt], passes).into_iter().map(Interpretable::IStmt));
+            },
+            Interpretable::IExpr(e) => top.push(Interpretable::IExpr(expr(e, passes))),
+        }
+    }
+    ast.top = top;
+}
+
+
+// Optimizes a list of statements run in the same scope.
+fn stmts(list: Vec<Stmt>, passes: &Passes) -> Vec<Stmt> {
+    let mut optimized = Vec::new();
+    for s in list {
+        let s = stmt(s, passes);
+        match s.kind {
+            StmtKind::SEmpty if passes.empty => (),
+            StmtKind::SBlock(inner) if passes.blocks && !inner.iter().any(declares) => {
+                optimized.extend(inner);
+            },
+            _ => optimized.push(s),
+        }
+    }
+    optimized
+}
+
+
+fn declares(stmt: &Stmt) -> bool {
+    matches!(stmt.kind, StmtKind::SVar(..) | StmtKind::SFun(..))
+}
+
+
+fn stmt(s: Stmt, passes: &Passes) -> Stmt {
+    use StmtKind::*;
+    let Stmt { kind, pos, doc } = s;
+    let kind = match kind {
+        SPrint(e) => SPrint(expr(e, passes)),
+        SVar(name, e) => SVar(name, e.map(|e| expr(e, passes))),
+        SExpr(e) => SExpr(expr(e, passes)),
+        SFun(name, ps, body) => SFun(name, params(ps, passes), Rc::new(stmt(Rc::unwrap_or_clone(body), passes))),
+        SReturn(e) => SReturn(expr(e, passes)),
+        SBlock(list) => SBlock(stmts(list, passes)),
+        SIf(cond, then, else_) => {
+            let cond = expr(cond, passes);
+            match truthiness(&cond) {
+                Some(true) if passes.branches => return stmt(*then, passes),
+                Some(false) if passes.branches => match else_ {
+                    Some(else_) => return stmt(*else_, passes),
+                    None => SEmpty,
+                },
+                _ => SIf(
+                    cond,
+                    Box::new(stmt(*then, passes)),
+                    else_.map(|e| Box::new(stmt(*e, passes))),
+                ),
+            }
+        },
+        SWhile(cond, body) => SWhile(expr(cond, passes), Box::new(stmt(*body, passes))),
+        SEmpty => SEmpty,
+    };
+    Stmt { kind, pos, doc }
+}
+
+
+fn params(ps: Vec<Param>, passes: &Passes) -> Vec<Param> {
+    ps.into_iter()
+        .map(|p| Param { default: p.default.map(|e| expr(e, passes)), ..p })
+        .collect()
+}
+
+
+fn expr(e: Expr, passes: &Passes) -> Expr {
+    use Expr::*;
+    let boxed = |e: Box<Expr>| Box::new(expr(*e, passes));
+    let e = match e {
+        EBinOp { op, left, right } => EBinOp { op, left: boxed(left), right: boxed(right) },
+        EUnaryOp { op, operand } => EUnaryOp { op, operand: boxed(operand) },
+        EGroup { expr } => EGroup { expr: boxed(expr) },
+        EAssign { name, expr } => EAssign { name, expr: boxed(expr) },
+        ELogicalOp { op, left, right, pos } => ELogicalOp { op, left: boxed(left), right: boxed(right), pos },
+        ECall { func, args, pos } => ECall {
+            func: boxed(func),
+            args: args.into_iter().map(|a| expr(a, passes)).collect(),
+            pos,
+        },
+        EFun { params: ps, body, pos } => EFun {
+            params: params(ps, passes),
+            body: Rc::new(stmt(Rc::unwrap_or_clone(body), passes)),
+            pos,
+        },
+        ESpread { expr } => ESpread { expr: boxed(expr) },
+        ENamed { name, expr, pos } => ENamed { name, expr: boxed(expr), pos },
+        EInterpolate { parts } => EInterpolate { parts: parts.into_iter().map(|p| expr(p, passes)).collect() },
+        e => e,
+    };
+    match passes.fold {
+        true => fold(e),
+        false => e,
+    }
+}
+
+
+// The literal an operator on literals evaluates to, or `e` unchanged if it
+// isn't one or fails, so the error still happens at run time.
+fn fold(e: Expr) -> Expr {
+    use Expr::*;
+    let constant = match &e {
+        EBinOp { left, right, .. } => is_literal(left) && is_literal(right),
+        EUnaryOp { operand, .. } => is_literal(operand),
+        EGroup { expr } => is_literal(expr),
+        _ => false,
+    };
+    if !constant || !bounded(&e) {
+        return e;
+    }
+    match eval(&e, &Environment::new()) {
+        Ok(value) => literal(&value).unwrap_or(e),
+        Err(_) => e,
+    }
+}
+
+
+// Whether the result of folding `e` has a bounded size: only repeating a
+// string can make a literal much larger than its operands.
+fn bounded(e: &Expr) -> bool {
+    use Expr::*;
+    match e {
+        EBinOp { op: Operator::Mul, left, right } => match (&**left, &**right) {
+            (EStr { value }, EInt { value: n }) | (EInt { value: n }, EStr { value }) => {
+                usize::try_from(*n).map_or(true, |n| value.len().saturating_mul(n) <= MAX_FOLDED_LEN)
+            },
+            _ => true,
+        },
+        _ => true,
+    }
+}
+
+
+fn is_literal(e: &Expr) -> bool {
+    use Expr::*;
+    matches!(e, EInt { .. } | EBigInt { .. } | ENumb { .. } | EStr { .. } | EBool { .. } | ENil)
+}
+
+
+fn literal(value: &LoxValue) -> Option<Expr> {
+    use Expr::*;
+    Some(match &**value {
+        LoxType::VInt(value) => EInt { value: *value },
+        LoxType::VBigInt(value) => EBigInt { value: value.clone() },
+        LoxType::VNumb(value) => ENumb { value: *value },
+        LoxType::VStr(value) => EStr { value: value.clone() },
+        LoxType::VBool(value) => EBool { value: *value },
+        LoxType::VNil => ENil,
+        _ => return None,
+    })
+}
+
+
+fn truthiness(e: &Expr) -> Option<bool> {
+    match e {
+        Expr::EBool { value: false } | Expr::ENil => Some(false),
+        e if is_literal(e) => Some(true),
+        _ => None,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::Backend;
+    use crate::golden;
+    use crate::parser::{parse, Dialect};
+    use crate::source::Source;
+    use crate::tokenizer::tokenize;
+    use pretty_assertions::assert_eq;
+
+    fn optimized(code: &str, passes: Passes) -> String {
+        let src = Source::from_string(code.to_string());
+        let tokens = tokenize(&src).unwrap();
+        let mut ast = parse(&tokens).unwrap();
+        optimize(&mut ast, &passes);
+        ast.to_string()
+    }
+
+    #[test]
+    fn test_fold() {
+        let fold = Passes { fold: true, ..Passes::default() };
+        assert_eq!(optimized("print (1 + 2) * -3;", fold), "print -9\n");
+        assert_eq!(optimized("print !(\"a\" * 2 == \"aa\");", fold), "print false\n");
+        assert_eq!(optimized("print 9223372036854775807 + 1;", fold), "print 9223372036854775808\n");
+        // operands that aren't literals, and errors, are left to run time
+        assert_eq!(optimized("print x + (1 + 1);", fold), "print (var x + 2)\n");
+        assert_eq!(optimized("print \"a\" - 1;", fold), "print (\"a\" - 1)\n");
+        // nor are repetitions too long to be worth a literal
+        assert_eq!(optimized("print \"ab\" * 3;", fold), "print \"ababab\"\n");
+        assert_eq!(optimized("print 600 * \"ab\";", fold), "print (600 * \"ab\")\n");
+    }
+
+    #[test]
+    fn test_branches_and_blocks() {
+        let code = "if 1 > 2 { print 1; } else { print 2; }\nif true { { print 3; } var x; }\nif nil { print 4; }";
+        assert_eq!(optimized(code, Passes { branches: true, ..Passes::default() }), "\
+if (1 > 2)
+  block
+    print 1
+else
+  block
+    print 2
+block
+  block
+    print 3
+  var x
+empty
+");
+        assert_eq!(optimized(code, Passes::all()), "\
+print 2
+block
+  print 3
+  var x
+");
+    }
+
+    #[test]
+    fn test_same_behavior() {
+        let code = "\
+var x = 1;
+if (2 * 3 == 6) {
+    { var x = 2; print x; } // expect: 2
+    { x = x + 1; }
+}
+fun f(a = 10 / 4) { return \"${a}\" * 2; }
+print f(); // expect: 2.52.5
+print x; // expect: 2
+fun never() { print \"ab\" * 100000000000; }
+print \"ok\"; // expect: ok
+print 1 - \"a\"; // expect runtime error: Cannot subtract Int from String
+";
+        for passes in [Passes::default(), Passes::all()] {
+            let failures = golden::check(&mut Source::from_string(code.to_string()), Dialect::Bwl, passes, Backend::Tree);
+            assert_eq!(failures, Vec::<String>::new());
+        }
+    }
+
+    #[test]
+    fn test_parse_passes() {
+        assert_eq!(Passes::parse("all"), Ok(Passes::all()));
+        assert_eq!(Passes::parse("fold, empty"), Ok(Passes { fold: true, empty: true, ..Passes::default() }));
+        assert_eq!(
+            Passes::parse("fold,inline"),
+            Err("unknown pass 'inline', expected 'all' or some of fold, branches, empty, blocks".to_string()),
+        );
+    }
+}
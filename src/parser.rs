@@ -1,8 +1,12 @@
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
+
 use prev_iter::PrevPeekable;
 
-use crate::ast::{Expr, Operator, Stmt, AST, Interpretable};
+use crate::ast::{Expr, Operator, Param, Stmt, AST, Interpretable};
 use crate::ast::Expr::*;
-use crate::ast::Stmt::*;
+use crate::ast::StmtKind::*;
 use crate::source::{FilePosition, SourceError};
 use crate::tokenizer::{Tokens, Token, TokenType, LiteralValue};
 use crate::tokenizer::TokenType::*;
@@ -15,6 +19,7 @@ const PARSE_ERROR: &'static str = "ParseError";
 pub struct ParseError {
     pos: Option<FilePosition>,
     msg: String,
+    eof: bool,
 }
 
 impl SourceError for ParseError {
@@ -36,14 +41,139 @@ impl ParseError {
         ParseError {
             pos: Some(pos),
             msg,
+            eof: false,
+        }
+    }
+
+    fn eof(pos: Option<FilePosition>, msg: String) -> ParseError {
+        ParseError {
+            pos,
+            msg,
+            eof: true,
+        }
+    }
+
+    /// Whether parsing failed because the tokens ran out, meaning more
+    /// input could still make the program valid.
+    pub fn at_eof(&self) -> bool {
+        self.eof
+    }
+}
+
+
+/// The syntax to parse. `Bwl` requires braces around bodies and no
+/// parentheses around conditions; `Classic` is the grammar from Crafting
+/// Interpreters, with `if (cond) stmt`, `while (cond) stmt` and
+/// `for (...) stmt`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Dialect {
+    #[default]
+    Bwl,
+    Classic,
+}
+
+impl Dialect {
+    /// The dialect named by a `// dialect: classic` or `// dialect: bwl`
+    /// comment in the comments and blank lines at the top of `content`.
+    pub fn from_pragma(content: &str) -> Option<Dialect> {
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() {
+                continue;
+            }
+            let comment = line.strip_prefix("//")?;
+            match comment.trim().strip_prefix("dialect:").map(str::trim) {
+                Some("classic") => return Some(Dialect::Classic),
+                Some("bwl") => return Some(Dialect::Bwl),
+                _ => (),
+            }
         }
+        None
+    }
+}
+
+
+/// The tokens being parsed, along with the dialect to parse them as.
+pub struct TokenStream<I>
+where
+    I: Iterator,
+    I::Item: Clone,
+{
+    iter: PrevPeekable<I>,
+    dialect: Dialect,
+    // The doc comments before each token, by the token's line and column.
+    docs: HashMap<(usize, usize), String>,
+}
+
+impl<I> TokenStream<I>
+where
+    I: Iterator,
+    I::Item: Clone,
+{
+    fn new(iter: I, dialect: Dialect) -> TokenStream<I> {
+        TokenStream {
+            iter: PrevPeekable::new(iter),
+            dialect,
+            docs: HashMap::new(),
+        }
+    }
+}
+
+impl<I> Deref for TokenStream<I>
+where
+    I: Iterator,
+    I::Item: Clone,
+{
+    type Target = PrevPeekable<I>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.iter
+    }
+}
+
+impl<I> DerefMut for TokenStream<I>
+where
+    I: Iterator,
+    I::Item: Clone,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.iter
     }
 }
 
 
 pub fn parse<'a>(tokens: &'a Tokens<'a>) -> Result<AST, ParseError> {
+    parse_dialect(tokens, Dialect::Bwl)
+}
+
+
+// Doc comments by the position of the token after them, with the text
+// of consecutive comments joined into lines.
+fn doc_comments(tokens: &Tokens) -> HashMap<(usize, usize), String> {
+    let mut docs = HashMap::new();
+    let mut lines = Vec::new();
+    for token in tokens.iter() {
+        match (token.get_type(), &token.literal) {
+            (DocComment, Some(LiteralValue::LString(text))) => lines.push(text.to_string()),
+            _ if !lines.is_empty() => {
+                docs.insert((token.pos.lineno, token.pos.linepos), lines.join("\n"));
+                lines.clear();
+            },
+            _ => (),
+        }
+    }
+    docs
+}
+
+
+fn is_code(token: &&Token) -> bool {
+    *token.get_type() != DocComment
+}
+
+
+pub fn parse_dialect<'a>(tokens: &'a Tokens<'a>, dialect: Dialect) -> Result<AST, ParseError> {
     let mut ast = AST::new();
-    let mut token_iter = PrevPeekable::new(tokens.iter());
+    let mut token_iter = TokenStream::new(tokens.iter().filter(is_code), dialect);
+    token_iter.docs = doc_comments(tokens);
 
     while let Some(_) = token_iter.peek() {
         ast.top.push(Interpretable::IStmt(declaration(&mut token_iter)?));
@@ -54,7 +184,7 @@ pub fn parse<'a>(tokens: &'a Tokens<'a>) -> Result<AST, ParseError> {
 
 
 pub fn parse_expr<'a>(tokens: &'a Tokens<'a>) -> Result<Expr, ParseError> {
-    let mut token_iter = PrevPeekable::new(tokens.iter());
+    let mut token_iter = TokenStream::new(tokens.iter().filter(is_code), Dialect::Bwl);
 
     let expr = expression(&mut token_iter)?;
 
@@ -64,6 +194,7 @@ pub fn parse_expr<'a>(tokens: &'a Tokens<'a>) -> Result<Expr, ParseError> {
             return Err(ParseError {
                 pos:None,
                 msg: "Failed to parse all tokens".to_string(),
+                eof: false,
             });
         },
     }
@@ -72,35 +203,48 @@ pub fn parse_expr<'a>(tokens: &'a Tokens<'a>) -> Result<Expr, ParseError> {
 }
 
 
-fn declaration<'a, I>(token_iter: &mut PrevPeekable<I>) -> Result<Stmt, ParseError>
+fn declaration<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Stmt, ParseError>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
     let token = match token_iter.peek() {
-        Some(token) => token,
-        None => return Ok(SEmpty),
+        Some(token) => *token,
+        None => return Ok(Stmt::new(SEmpty, _next_pos(token_iter))),
     };
+    let doc = token_iter.docs.remove(&(token.pos.lineno, token.pos.linepos));
 
     match token.get_type() {
-        Fun => function_declaration(token_iter),
-        Var => var_declaration(token_iter),
+        Fun => Ok(function_declaration(token_iter)?.with_doc(doc)),
+        Var => Ok(var_declaration(token_iter)?.with_doc(doc)),
         _ => statement(token_iter),
     }
 }
 
 
-fn _function_params<'a, I>(token_iter: &mut PrevPeekable<I>) -> Result<Vec<String>, ParseError>
+fn _function_params<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Vec<Param>, ParseError>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
     let mut params = Vec::new();
 
     while !_next_is(token_iter, RightParen) {
-        params.push(expect(
+        let pos = _next_pos(token_iter);
+        let rest = _next_is(token_iter, Ellipsis);
+        if rest { token_iter.next(); };
+        let name = expect(
             token_iter,
             Identifier,
             "Expected parameter name".to_string(),
-        )?.lexeme.to_string());
+        )?.lexeme.to_string();
+        let default = match !rest && _next_is(token_iter, Equal) {
+            true => {
+                token_iter.next();
+                Some(expression(token_iter)?)
+            },
+            false => None,
+        };
+        params.push(Param { name, default, rest });
+        _check_param(&params, pos)?;
         if _next_is(token_iter, Comma) { token_iter.next(); };
     }
 
@@ -111,26 +255,63 @@ where
 }
 
 
-fn function_declaration<'a, I>(token_iter: &mut PrevPeekable<I>) -> Result<Stmt, ParseError>
+// Checks that the last of `params`, at `pos`, can follow the ones before
+// it: a rest parameter has to come last, and defaults can't be skipped.
+fn _check_param(params: &[Param], pos: FilePosition) -> Result<(), ParseError> {
+    let Some((last, before)) = params.split_last() else { return Ok(()) };
+    let msg = if before.iter().any(|p| p.rest) {
+        "A rest parameter must be the last parameter"
+    } else if last.default.is_none() && !last.rest && before.iter().any(|p| p.default.is_some()) {
+        "A parameter without a default cannot follow one with a default"
+    } else {
+        return Ok(());
+    };
+    Err(ParseError::new(pos, msg.to_string()))
+}
+
+
+fn function_declaration<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Stmt, ParseError>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
+    let pos = _next_pos(token_iter);
     token_iter.next(); // consume fun token
 
     let id = expect(token_iter, Identifier, "Expected function name".to_string())?;
+    let (params, body) = function_rest(token_iter)?;
+
+    Ok(Stmt::new(SFun(id.lexeme.to_string(), params, Rc::new(body)), pos))
+}
+
+
+// The parameters and body of a function after its name, if any.
+fn function_rest<'a, I>(token_iter: &mut TokenStream<I>) -> Result<(Vec<Param>, Stmt), ParseError>
+where
+    I: Iterator<Item = &'a Token<'a>>,
+{
     expect(token_iter, LeftParen, "Expected '(' to begin function argument list".to_string())?;
     let params = _function_params(token_iter)?;
     expect(token_iter, RightParen, "Expected ')' after function parameters".to_string())?;
-    let body = block(token_iter)?;
+    Ok((params, block(token_iter)?))
+}
 
-    Ok(SFun(id.lexeme.to_string(), params, Box::new(body)))
+
+fn function_expression<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Expr, ParseError>
+where
+    I: Iterator<Item = &'a Token<'a>>,
+{
+    let pos = _next_pos(token_iter);
+    token_iter.next(); // consume fun token
+    let (params, body) = function_rest(token_iter)?;
+    Ok(EFun { params, body: Rc::new(body), pos })
 }
 
 
-fn var_declaration<'a, I>(token_iter: &mut PrevPeekable<I>) -> Result<Stmt, ParseError>
+fn var_declaration<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Stmt, ParseError>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
+    let pos = _next_pos(token_iter);
     token_iter.next();
 
     let id = expect(
@@ -150,17 +331,17 @@ where
     };
 
     expect(token_iter, SemiColon, "Expected ';' after variable declaration".to_string())?;
-    Ok(SVar(id.lexeme.to_string(), init))
+    Ok(Stmt::new(SVar(id.lexeme.to_string(), init), pos))
 }
 
 
-fn statement<'a, I>(token_iter: &mut PrevPeekable<I>) -> Result<Stmt, ParseError>
+fn statement<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Stmt, ParseError>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
     let token = match token_iter.peek() {
         Some(token) => token,
-        None => return Ok(SEmpty),
+        None => return Ok(Stmt::new(SEmpty, _next_pos(token_iter))),
     };
 
     match token.get_type() {
@@ -176,7 +357,7 @@ where
 }
 
 
-fn _for_initializer<'a, I>(token_iter: &mut PrevPeekable<I>) -> Result<Option<Stmt>, ParseError>
+fn _for_initializer<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Option<Stmt>, ParseError>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
@@ -196,7 +377,7 @@ where
 }
 
 
-fn _for_condition<'a, I>(token_iter: &mut PrevPeekable<I>) -> Result<Expr, ParseError>
+fn _for_condition<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Expr, ParseError>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
@@ -219,7 +400,7 @@ where
 }
 
 
-fn _for_increment<'a, I>(token_iter: &mut PrevPeekable<I>) -> Result<Option<Expr>, ParseError>
+fn _for_increment<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Option<Expr>, ParseError>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
@@ -229,35 +410,35 @@ where
     )?;
 
     match token.get_type() {
-        RightParen => {
-            token_iter.next();
-            return Ok(None)
-        },
+        RightParen => Ok(None),
         _ => Ok(Some(expression(token_iter)?)),
     }
 }
 
 
-fn for_statement<'a, I>(token_iter: &mut PrevPeekable<I>) -> Result<Stmt, ParseError>
+fn for_statement<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Stmt, ParseError>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
+    let pos = _next_pos(token_iter);
     token_iter.next();
     expect(token_iter, LeftParen, "Expected '(' at start of for setup".to_string())?;
     let init = _for_initializer(token_iter)?;
     let cond = _for_condition(token_iter)?;
+    let incr_pos = _next_pos(token_iter);
     let incr = _for_increment(token_iter)?;
     expect(token_iter, RightParen, "Expected ')' at end of for setup".to_string())?;
-    let mut body = block(token_iter)?;
+    let mut body = body(token_iter)?;
 
     if let Some(expr) = incr {
-        body = SBlock(vec![body, SExpr(expr)]);
+        // at the increment, to tell it from the loop for coverage
+        body = Stmt::new(SBlock(vec![body, Stmt::new(SExpr(expr), incr_pos)]), incr_pos);
     }
 
-    body = SWhile(cond, Box::new(body));
+    body = Stmt::new(SWhile(cond, Box::new(body)), pos);
 
     if let Some(stmt) = init {
-        body = SBlock(vec![stmt, body]);
+        body = Stmt::new(SBlock(vec![stmt, body]), pos);
     }
 
 
@@ -265,7 +446,7 @@ where
 }
 
 
-fn else_statement<'a, I>(token_iter: &mut PrevPeekable<I>) -> Result<Stmt, ParseError>
+fn else_statement<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Stmt, ParseError>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
@@ -276,6 +457,10 @@ where
         "expected if or code block after else".to_string(),
     )?;
 
+    if token_iter.dialect == Dialect::Classic {
+        return statement(token_iter);
+    }
+
     // next should be if or block or it's an error
     match token.get_type() {
         If => if_statement(token_iter),
@@ -290,13 +475,14 @@ where
 }
 
 
-fn if_statement<'a, I>(token_iter: &mut PrevPeekable<I>) -> Result<Stmt, ParseError>
+fn if_statement<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Stmt, ParseError>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
+    let pos = _next_pos(token_iter);
     token_iter.next();
-    let cond = expression(token_iter)?;
-    let then = block(token_iter)?;
+    let cond = condition(token_iter)?;
+    let then = body(token_iter)?;
 
     let else_ = match token_iter.peek() {
         Some(token) =>  match token.get_type() {
@@ -306,14 +492,15 @@ where
         None => None,
     };
 
-    Ok(SIf(cond, Box::new(then), else_))
+    Ok(Stmt::new(SIf(cond, Box::new(then), else_), pos))
 }
 
 
-fn return_statement<'a, I>(token_iter: &mut PrevPeekable<I>) -> Result<Stmt, ParseError>
+fn return_statement<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Stmt, ParseError>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
+    let pos = _next_pos(token_iter);
     token_iter.next(); // consume return token
 
     let expr = match _next_is(token_iter, SemiColon) {
@@ -321,34 +508,69 @@ where
         false => expression(token_iter)?,
     };
     expect(token_iter, SemiColon,"Expected ';' at end of return statement".to_string())?;
-    Ok(SReturn(expr))
+    Ok(Stmt::new(SReturn(expr), pos))
 }
 
 
-fn print_statement<'a, I>(token_iter: &mut PrevPeekable<I>) -> Result<Stmt, ParseError>
+fn print_statement<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Stmt, ParseError>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
+    let pos = _next_pos(token_iter);
     token_iter.next();
     let expr = expression(token_iter)?;
     expect(token_iter, SemiColon, "Expected ';' at end of print statement".to_string())?;
-    Ok(SPrint(expr))
+    Ok(Stmt::new(SPrint(expr), pos))
 }
 
 
-fn while_statement<'a, I>(token_iter: &mut PrevPeekable<I>) -> Result<Stmt, ParseError>
+fn while_statement<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Stmt, ParseError>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
+    let pos = _next_pos(token_iter);
     token_iter.next();
+    let cond = condition(token_iter)?;
+    let body = body(token_iter)?;
+
+    Ok(Stmt::new(SWhile(cond, Box::new(body)), pos))
+}
+
+
+// The condition of an `if` or `while`, which `Dialect::Classic` wraps in
+// parentheses.
+fn condition<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Expr, ParseError>
+where
+    I: Iterator<Item = &'a Token<'a>>,
+{
+    if token_iter.dialect == Dialect::Bwl {
+        return expression(token_iter);
+    }
+
+    expect(token_iter, LeftParen, "Expected '(' before condition".to_string())?;
     let cond = expression(token_iter)?;
-    let body = block(token_iter)?;
+    expect(token_iter, RightParen, "Expected ')' after condition".to_string())?;
+    Ok(cond)
+}
 
-    Ok(SWhile(cond, Box::new(body)))
+
+// The body of an `if`, `while` or `for`: a block, or in `Dialect::Classic`
+// any statement.
+fn body<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Stmt, ParseError>
+where
+    I: Iterator<Item = &'a Token<'a>>,
+{
+    match token_iter.dialect {
+        Dialect::Bwl => block(token_iter),
+        Dialect::Classic => {
+            peek_token(token_iter, "Expected statement for body".to_string())?;
+            statement(token_iter)
+        },
+    }
 }
 
 
-fn _next_is<'a, I>(token_iter: &mut PrevPeekable<I>, ttype: TokenType) -> bool
+fn _next_is<'a, I>(token_iter: &mut TokenStream<I>, ttype: TokenType) -> bool
 where
     I: Iterator<Item = &'a Token<'a>>
 {
@@ -359,33 +581,57 @@ where
 }
 
 
-fn block<'a, I>(token_iter: &mut PrevPeekable<I>) -> Result<Stmt, ParseError>
+fn _last_pos<'a, I>(token_iter: &mut TokenStream<I>) -> Option<FilePosition>
+where
+    I: Iterator<Item = &'a Token<'a>>
+{
+    token_iter.prev_peek().map(|token| token.get_position())
+}
+
+
+fn _next_pos<'a, I>(token_iter: &mut TokenStream<I>) -> FilePosition
+where
+    I: Iterator<Item = &'a Token<'a>>
+{
+    if let Some(token) = token_iter.peek() {
+        return token.get_position();
+    }
+    match token_iter.prev_peek() {
+        Some(token) => token.get_position(),
+        None => FilePosition::new(0, 0),
+    }
+}
+
+
+fn block<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Stmt, ParseError>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
+    let pos = _next_pos(token_iter);
     expect(token_iter, LeftBrace, "Expected '{' at start of block".to_string())?;
     let mut stmts = Vec::new();
 
-    while !_next_is(token_iter, RightBrace) {
+    while token_iter.peek().is_some() && !_next_is(token_iter, RightBrace) {
         stmts.push(declaration(token_iter)?);
     }
 
     expect(token_iter, RightBrace, "Expected '}' at end of block".to_string())?;
-    Ok(SBlock(stmts))
+    Ok(Stmt::new(SBlock(stmts), pos))
 }
 
 
-fn expression_statement<'a, I>(token_iter: &mut PrevPeekable<I>) -> Result<Stmt, ParseError>
+fn expression_statement<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Stmt, ParseError>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
+    let pos = _next_pos(token_iter);
     let expr = expression(token_iter)?;
     expect(token_iter, SemiColon, "Expected ';' at end of expression statment".to_string())?;
-    Ok(SExpr(expr))
+    Ok(Stmt::new(SExpr(expr), pos))
 }
 
 
-fn expression<'a, I>(token_iter: &mut PrevPeekable<I>) -> Result<Expr, ParseError>
+fn expression<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Expr, ParseError>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
@@ -393,17 +639,18 @@ where
 }
 
 
-fn assignment_statement<'a, I>(token_iter: &mut PrevPeekable<I>) -> Result<Stmt, ParseError>
+fn assignment_statement<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Stmt, ParseError>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
-    let stmt = SExpr(assignment(token_iter)?);
+    let pos = _next_pos(token_iter);
+    let stmt = Stmt::new(SExpr(assignment(token_iter)?), pos);
     expect(token_iter, SemiColon, "Expected ';' at end of assignment statement".to_string())?;
     Ok(stmt)
 }
 
 
-fn assignment<'a, I>(token_iter: &mut PrevPeekable<I>) -> Result<Expr, ParseError>
+fn assignment<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Expr, ParseError>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
@@ -428,7 +675,7 @@ where
 }
 
 
-fn _is_or<'a, I>(token_iter: &mut PrevPeekable<I>) -> bool
+fn _is_or<'a, I>(token_iter: &mut TokenStream<I>) -> bool
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
@@ -439,18 +686,20 @@ where
 }
 
 
-fn or<'a, I>(token_iter: &mut PrevPeekable<I>) -> Result<Expr, ParseError>
+fn or<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Expr, ParseError>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
     let mut expr = and(token_iter)?;
 
     while _is_or(token_iter) {
+        let pos = _next_pos(token_iter);
         token_iter.next();
         expr = ELogicalOp {
             op: Operator::Or,
             left: Box::new(expr),
-            right: Box::new(and(token_iter)?)
+            right: Box::new(and(token_iter)?),
+            pos,
         };
     }
 
@@ -458,7 +707,7 @@ where
 }
 
 
-fn _is_and<'a, I>(token_iter: &mut PrevPeekable<I>) -> bool
+fn _is_and<'a, I>(token_iter: &mut TokenStream<I>) -> bool
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
@@ -469,18 +718,20 @@ where
 }
 
 
-fn and<'a, I>(token_iter: &mut PrevPeekable<I>) -> Result<Expr, ParseError>
+fn and<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Expr, ParseError>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
     let mut expr = equality(token_iter)?;
 
     while _is_and(token_iter) {
+        let pos = _next_pos(token_iter);
         token_iter.next();
         expr = ELogicalOp {
             op: Operator::And,
             left: Box::new(expr),
-            right: Box::new(equality(token_iter)?)
+            right: Box::new(equality(token_iter)?),
+            pos,
         };
     }
 
@@ -488,7 +739,7 @@ where
 }
 
 
-fn _equality<'a, I>(token_iter: &mut PrevPeekable<I>) -> Option<Operator>
+fn _equality<'a, I>(token_iter: &mut TokenStream<I>) -> Option<Operator>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
@@ -501,7 +752,7 @@ where
 }
 
 
-fn equality<'a, I>(token_iter: &mut PrevPeekable<I>) -> Result<Expr, ParseError>
+fn equality<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Expr, ParseError>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
@@ -517,7 +768,7 @@ where
 }
 
 
-fn _comparison<'a, I>(token_iter: &mut PrevPeekable<I>) -> Option<Operator>
+fn _comparison<'a, I>(token_iter: &mut TokenStream<I>) -> Option<Operator>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
@@ -532,7 +783,7 @@ where
 }
 
 
-fn comparison<'a, I>(token_iter: &mut PrevPeekable<I>) -> Result<Expr, ParseError>
+fn comparison<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Expr, ParseError>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
@@ -548,7 +799,7 @@ where
 }
 
 
-fn _term<'a, I>(token_iter: &mut PrevPeekable<I>) -> Option<Operator>
+fn _term<'a, I>(token_iter: &mut TokenStream<I>) -> Option<Operator>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
@@ -561,7 +812,7 @@ where
 }
 
 
-fn term<'a, I>(token_iter: &mut PrevPeekable<I>) -> Result<Expr, ParseError>
+fn term<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Expr, ParseError>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
@@ -577,7 +828,7 @@ where
 }
 
 
-fn _factor<'a, I>(token_iter: &mut PrevPeekable<I>) -> Option<Operator>
+fn _factor<'a, I>(token_iter: &mut TokenStream<I>) -> Option<Operator>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
@@ -585,12 +836,13 @@ where
     match token.get_type() {
         Slash => Some(Operator::Div),
         Star => Some(Operator::Mul),
+        Percent => Some(Operator::Mod),
         _ => None,
     }
 }
 
 
-fn factor<'a, I>(token_iter: &mut PrevPeekable<I>) -> Result<Expr, ParseError>
+fn factor<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Expr, ParseError>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
@@ -606,7 +858,7 @@ where
 }
 
 
-fn _unary<'a, I>(token_iter: &mut PrevPeekable<I>) -> Option<Operator>
+fn _unary<'a, I>(token_iter: &mut TokenStream<I>) -> Option<Operator>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
@@ -619,7 +871,7 @@ where
 }
 
 
-fn unary<'a, I>(token_iter: &mut PrevPeekable<I>) -> Result<Expr, ParseError>
+fn unary<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Expr, ParseError>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
@@ -633,14 +885,27 @@ where
 }
 
 
-fn _function_args<'a, I>(token_iter: &mut PrevPeekable<I>) -> Result<Vec<Expr>, ParseError>
+fn _function_args<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Vec<Expr>, ParseError>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
     let mut args = Vec::new();
+    let mut named = false;
 
     while !_next_is(token_iter, RightParen) {
-        args.push(expression(token_iter)?);
+        let pos = _next_pos(token_iter);
+        args.push(match argument(token_iter)? {
+            EVar { name } if _next_is(token_iter, Colon) => {
+                token_iter.next();
+                named = true;
+                ENamed { name, expr: Box::new(expression(token_iter)?), pos }
+            },
+            _ if named => return Err(ParseError::new(
+                pos,
+                "Positional arguments must come before named arguments".to_string(),
+            )),
+            arg => arg,
+        });
         if _next_is(token_iter, Comma) { token_iter.next(); };
     }
 
@@ -651,7 +916,20 @@ where
 }
 
 
-fn call<'a, I>(token_iter: &mut PrevPeekable<I>) -> Result<Expr, ParseError>
+// An expression passed to a call, which can be spread with `...`.
+fn argument<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Expr, ParseError>
+where
+    I: Iterator<Item = &'a Token<'a>>,
+{
+    if _next_is(token_iter, Ellipsis) {
+        token_iter.next();
+        return Ok(ESpread { expr: Box::new(expression(token_iter)?) });
+    }
+    expression(token_iter)
+}
+
+
+fn call<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Expr, ParseError>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
@@ -659,10 +937,11 @@ where
 
     loop {
         if _next_is(token_iter, LeftParen) {
+            let pos = _next_pos(token_iter);
             token_iter.next(); // because we know we have left paren
             let args = _function_args(token_iter)?;
             expect(token_iter, RightParen, "Expected ')' on call".to_string())?;
-            expr = ECall { func: Box::new(expr), args };
+            expr = ECall { func: Box::new(expr), args, pos };
         } else {
             break
         }
@@ -672,7 +951,7 @@ where
 }
 
 
-fn _primary<'a, I>(token_iter: &mut PrevPeekable<I>) -> Option<Expr>
+fn _primary<'a, I>(token_iter: &mut TokenStream<I>) -> Option<Expr>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
@@ -681,11 +960,13 @@ where
         False => Some(EBool { value: false }),
         True => Some(EBool { value: true }),
         Nil => Some(ENil),
-        Number => match token.literal {
-            Some(LiteralValue::LNumber(value)) => Some(ENumb { value }),
+        Number => match &token.literal {
+            Some(LiteralValue::LInt(value)) => Some(EInt { value: *value }),
+            Some(LiteralValue::LBigInt(value)) => Some(EBigInt { value: value.clone() }),
+            Some(LiteralValue::LNumber(value)) => Some(ENumb { value: *value }),
             _ => None,
         },
-        Str => match token.literal {
+        Str => match &token.literal {
             Some(LiteralValue::LString(value)) => Some(EStr { value: value.to_string() }),
             _ => None,
         },
@@ -697,10 +978,17 @@ where
 }
 
 
-fn primary<'a, I>(token_iter: &mut PrevPeekable<I>) -> Result<Expr, ParseError>
+fn primary<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Expr, ParseError>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
+    if _next_is(token_iter, Interpolation) {
+        return interpolation(token_iter);
+    }
+    if _next_is(token_iter, Fun) {
+        return function_expression(token_iter);
+    }
+
     match _primary(token_iter) {
         Some(expr) => {
             token_iter.next();
@@ -711,10 +999,43 @@ where
 }
 
 
-fn group<'a, I>(token_iter: &mut PrevPeekable<I>) -> Result<Expr, ParseError>
+// A string with interpolated expressions: an `Interpolation` token before
+// each expression and a `Str` token after the last.
+fn interpolation<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Expr, ParseError>
 where
     I: Iterator<Item = &'a Token<'a>>,
 {
+    let mut parts = Vec::new();
+    while let Some(token) = token_iter.next() {
+        if let Some(LiteralValue::LString(value)) = &token.literal {
+            if !value.is_empty() {
+                parts.push(EStr { value: value.to_string() });
+            }
+        }
+        if *token.get_type() == Str {
+            break;
+        }
+
+        parts.push(expression(token_iter)?);
+        let token = peek_token(token_iter, "Expected '}' to close interpolation".to_string())?;
+        if !matches!(token.get_type(), Interpolation | Str) {
+            return Err(ParseError::new(
+                token.get_position(),
+                format!("Expected '}}' to close interpolation, found {}", token.get_type()),
+            ));
+        }
+    }
+    Ok(EInterpolate { parts })
+}
+
+
+// A parenthesized expression, or the parameters of an arrow function:
+// both are parsed as a list of expressions until we know which it is.
+fn group<'a, I>(token_iter: &mut TokenStream<I>) -> Result<Expr, ParseError>
+where
+    I: Iterator<Item = &'a Token<'a>>,
+{
+    let open = _next_pos(token_iter);
     match token_iter.peek() {
         Some(token) if *token.get_type() == LeftParen => {
             token_iter.next();
@@ -729,23 +1050,92 @@ where
         },
         None => {
             return Err(
-                ParseError {
-                    pos: None,
-                    msg: "invalid expression".to_string(),
-                },
+                ParseError::eof(_last_pos(token_iter), "invalid expression".to_string()),
             )
         },
     }
 
-    let expr = expression(token_iter)?;
-    expect(token_iter, RightParen, "Expected ')' to close group".to_string())?;
+    let mut exprs = Vec::new();
+    let mut comma = None;
+    while !_next_is(token_iter, RightParen) {
+        exprs.push(argument(token_iter)?);
+        if !_next_is(token_iter, Comma) {
+            break;
+        }
+        comma = comma.or(Some(_next_pos(token_iter)));
+        token_iter.next();
+    }
+    let close = expect(token_iter, RightParen, "Expected ')' to close group".to_string())?;
+
+    if _next_is(token_iter, Arrow) {
+        return arrow_function(token_iter, exprs, open);
+    }
+    if exprs.len() == 1 && comma.is_none() && !matches!(exprs[0], ESpread { .. }) {
+        return Ok(EGroup { expr: Box::new(exprs.remove(0)) });
+    }
+
+    // anything else is only valid as the parameters of an arrow function,
+    // so a list that can't be those was meant as a group
+    match comma {
+        Some(comma) if !exprs.iter().all(|e| as_param(e.clone()).is_some()) => {
+            Err(ParseError::new(comma, "Expected ')' to close group".to_string()))
+        },
+        _ => Err(ParseError::new(
+            close.get_position(),
+            "Expected '=>' after arrow function parameters".to_string(),
+        )),
+    }
+}
 
-    Ok(EGroup { expr: Box::new(expr) })
+
+// The parameter an expression in the parentheses of an arrow function
+// stands for, if any: defaults are assignments and rest parameters spreads.
+fn as_param(expr: Expr) -> Option<Param> {
+    match expr {
+        EVar { name } => Some(Param::new(name)),
+        EAssign { name, expr } => Some(Param { name, default: Some(*expr), rest: false }),
+        ESpread { expr } => match *expr {
+            EVar { name } => Some(Param { name, default: None, rest: true }),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+
+// The rest of an arrow function starting at `pos` after its parameters,
+// which were parsed as expressions: a block, or an expression to return.
+fn arrow_function<'a, I>(
+    token_iter: &mut TokenStream<I>,
+    exprs: Vec<Expr>,
+    pos: FilePosition,
+) -> Result<Expr, ParseError>
+where
+    I: Iterator<Item = &'a Token<'a>>,
+{
+    let arrow = _next_pos(token_iter);
+    let mut params = Vec::new();
+    for expr in exprs {
+        params.push(as_param(expr).ok_or_else(|| {
+            ParseError::new(arrow, "Arrow function parameters must be names".to_string())
+        })?);
+        _check_param(&params, arrow)?;
+    }
+    token_iter.next(); // consume =>
+
+    let body = match _next_is(token_iter, LeftBrace) {
+        true => block(token_iter)?,
+        false => {
+            let pos = _next_pos(token_iter);
+            Stmt::new(SReturn(expression(token_iter)?), pos)
+        },
+    };
+    Ok(EFun { params, body: Rc::new(body), pos })
 }
 
 
 fn peek_token<'a, I>(
-    token_iter: &mut PrevPeekable<I>,
+    token_iter: &mut TokenStream<I>,
     msg: String,
 ) -> Result<&'a Token<'a>, ParseError>
 where
@@ -756,33 +1146,28 @@ where
         None => {
             token_iter.next();
             let last = token_iter.prev().unwrap();
-            return Err(ParseError::new(
-                last.get_position(),
+            Err(ParseError::eof(
+                Some(last.get_position()),
                 msg,
-            ));
+            ))
         },
     }
 }
 
 
 fn expect<'a, I>(
-    token_iter: &mut PrevPeekable<I>,
+    token_iter: &mut TokenStream<I>,
     ttype: TokenType,
     msg: String,
 ) -> Result<&'a Token<'a>, ParseError>
 where
     I: Iterator<Item = &'a Token<'a>>
 {
-    let make_err = |t: Option<&Token>| -> Result<&'a Token<'a>, ParseError> {
-        Err(match t {
-            Some(token) => ParseError::new(
-                token.get_position(),
-                msg,
-            ),
-            None => ParseError {
-                pos: None,
-                msg,
-            },
+    let make_err = |t: Option<&Token>, eof: bool| -> Result<&'a Token<'a>, ParseError> {
+        Err(ParseError {
+            pos: t.map(|token| token.get_position()),
+            msg,
+            eof,
         })
     };
 
@@ -792,9 +1177,9 @@ where
     let prev = token_iter.prev_peek();
     match (prev, next) {
         (_, Some(token)) if *token.get_type() == ttype => Ok(token),
-        (Some(token), _) => make_err(Some(token)),
-        (None, Some(token)) => make_err(Some(token)),
-        (None, None) => make_err(None),
+        (Some(token), next) => make_err(Some(token), next.is_none()),
+        (None, Some(token)) => make_err(Some(token), false),
+        (None, None) => make_err(None, true),
     }
 }
 
@@ -805,6 +1190,14 @@ mod tests {
     use crate::source::FilePosition;
     use pretty_assertions::assert_eq;
 
+    fn parse_as(code: &str, dialect: Dialect) -> Result<String, String> {
+        let src = crate::source::Source::from_string(code.to_string());
+        let tokens = crate::tokenizer::tokenize(&src).unwrap();
+        parse_dialect(&tokens, dialect)
+            .map(|ast| ast.to_string())
+            .map_err(|e| e.get_message().to_string())
+    }
+
     #[test]
     fn test_add() {
         let tokens = vec![
@@ -969,4 +1362,135 @@ mod tests {
             },
         );
     }
+
+    #[test]
+    fn test_classic_dialect() {
+        assert_eq!(
+            parse_as("if (x) print 1; else print 2;", Dialect::Classic).unwrap(),
+            "if var x\n  print 1\nelse\n  print 2\n",
+        );
+        assert_eq!(
+            parse_as("while (x) x = x - 1;", Dialect::Classic).unwrap(),
+            "while var x\n  expr x = (var x - 1)\n",
+        );
+        assert_eq!(
+            parse_as("for (var i = 0; i < 2;) print i;", Dialect::Classic).unwrap(),
+            "block\n  var i = 0\n  while (var i < 2)\n    print var i\n",
+        );
+        assert!(parse_as("if x print 1;", Dialect::Classic).is_err());
+        assert!(parse_as("if (x) print 1;", Dialect::Bwl).is_err());
+        assert!(parse_as("while x x = 1;", Dialect::Bwl).is_err());
+    }
+
+    #[test]
+    fn test_interpolation() {
+        assert_eq!(
+            parse_as("print \"a ${x} b ${f(1)}\";", Dialect::Bwl).unwrap(),
+            "print \"a ${var x} b ${var f(1)}\"\n",
+        );
+        assert_eq!(
+            parse_as("print \"${x y}\";", Dialect::Bwl).unwrap_err(),
+            "Expected '}' to close interpolation, found Identifier",
+        );
+    }
+
+    #[test]
+    fn test_doc_comments() {
+        let src = crate::source::Source::from_string("\
+/// Adds things.
+/// Two of them.
+fun add(a, b) {
+    /// The answer.
+    var sum = a /// not attached
+        + b;
+    return sum;
+}
+/// Not a declaration.
+print add(1, 2);
+var plain;
+".to_string());
+        let tokens = crate::tokenizer::tokenize(&src).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let docs: Vec<_> = ast.documented().iter()
+            .map(|s| (s.declared_name().unwrap(), s.doc.clone().unwrap()))
+            .collect();
+        assert_eq!(docs, vec![
+            ("add", "Adds things.\nTwo of them.".to_string()),
+            ("sum", "The answer.".to_string()),
+        ]);
+        assert!(ast.to_string().starts_with("/// Adds things.\n/// Two of them.\nfun add(a, b)\n"));
+    }
+
+    #[test]
+    fn test_anonymous_functions() {
+        assert_eq!(
+            parse_as("var f = (a, b) => a * b;", Dialect::Bwl).unwrap(),
+            "var f = (a, b) => (var a * var b)\n",
+        );
+        assert_eq!(
+            parse_as("g(fun (x) { return x; }, () => { return 1; });", Dialect::Bwl).unwrap(),
+            "expr var g(fun(x) { ... }, fun() { ... })\n",
+        );
+        assert_eq!(parse_as("print (1);", Dialect::Bwl).unwrap(), "print (1)\n");
+        assert_eq!(
+            parse_as("var f = (a, 1) => a;", Dialect::Bwl).unwrap_err(),
+            "Arrow function parameters must be names",
+        );
+        assert_eq!(
+            parse_as("print (1, 2);", Dialect::Bwl).unwrap_err(),
+            "Expected ')' to close group",
+        );
+        assert_eq!(
+            parse_as("print (1,);", Dialect::Bwl).unwrap_err(),
+            "Expected ')' to close group",
+        );
+        assert_eq!(
+            parse_as("var f = (a,) => a;", Dialect::Bwl).unwrap(),
+            "var f = (a) => var a\n",
+        );
+        assert_eq!(
+            parse_as("print (a, b);", Dialect::Bwl).unwrap_err(),
+            "Expected '=>' after arrow function parameters",
+        );
+    }
+
+    #[test]
+    fn test_default_and_rest_params() {
+        assert_eq!(
+            parse_as("fun f(a, b = a + 1, ...rest) { g(...rest); }", Dialect::Bwl).unwrap(),
+            "fun f(a, b = (var a + 1), ...rest)\n  block\n    expr var g(...var rest)\n",
+        );
+        assert_eq!(
+            parse_as("var f = (a = 1, ...rest) => rest;", Dialect::Bwl).unwrap(),
+            "var f = (a = 1, ...rest) => var rest\n",
+        );
+        assert_eq!(
+            parse_as("fun f(...a, b) {}", Dialect::Bwl).unwrap_err(),
+            "A rest parameter must be the last parameter",
+        );
+        assert_eq!(
+            parse_as("var f = (a = 1, b) => b;", Dialect::Bwl).unwrap_err(),
+            "A parameter without a default cannot follow one with a default",
+        );
+    }
+
+    #[test]
+    fn test_named_args() {
+        assert_eq!(
+            parse_as("draw(1, height: 2 * 3);", Dialect::Bwl).unwrap(),
+            "expr var draw(1, height: (2 * 3))\n",
+        );
+        assert_eq!(
+            parse_as("draw(width: 1, 2);", Dialect::Bwl).unwrap_err(),
+            "Positional arguments must come before named arguments",
+        );
+    }
+
+    #[test]
+    fn test_dialect_pragma() {
+        assert_eq!(Dialect::from_pragma("// dialect: classic\nprint 1;"), Some(Dialect::Classic));
+        assert_eq!(Dialect::from_pragma("// a test\n\n//dialect: bwl\n"), Some(Dialect::Bwl));
+        assert_eq!(Dialect::from_pragma("print 1;\n// dialect: classic\n"), None);
+        assert_eq!(Dialect::from_pragma("// dialect: other\n"), None);
+    }
 }
@@ -0,0 +1,246 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::hooks::Hook;
+use crate::value::{LoxType, LoxValue};
+
+
+const SCRIPT: &str = "<script>";
+
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FunctionStats {
+    pub calls: usize,
+    pub inclusive: Duration,
+    pub exclusive: Duration,
+}
+
+
+struct Activation {
+    key: String,
+    start: Instant,
+    children: Duration,
+}
+
+
+/// Call counts and timings per Lox function, keyed by the function name
+/// and the line it was defined on, e.g. `fact:5`. Anonymous functions,
+/// which all share a name, also have the column, e.g. `lambda:7:12`.
+pub struct Profile {
+    pub functions: HashMap<String, FunctionStats>,
+    folded: HashMap<String, Duration>,
+    stack: Vec<Activation>,
+    start: Instant,
+    in_functions: Duration,
+    total: Duration,
+}
+
+impl Profile {
+    pub fn new() -> Rc<RefCell<Profile>> {
+        Rc::new(RefCell::new(Profile {
+            functions: HashMap::new(),
+            folded: HashMap::new(),
+            stack: Vec::new(),
+            start: Instant::now(),
+            in_functions: Duration::ZERO,
+            total: Duration::ZERO,
+        }))
+    }
+
+    fn key(func: &LoxValue) -> String {
+        match &**func {
+            LoxType::VCallable(name, _, _, _, pos) if name == "lambda" => {
+                format!("{}:{}:{}", name, pos.lineno, pos.linepos)
+            },
+            LoxType::VCallable(name, _, _, _, pos) => format!("{}:{}", name, pos.lineno),
+            _ => func.value_string(),
+        }
+    }
+
+    fn stack_path(&self) -> String {
+        let mut path = SCRIPT.to_string();
+        for activation in self.stack.iter() {
+            path.push(';');
+            path.push_str(&activation.key);
+        }
+        path
+    }
+
+    fn enter(&mut self, func: &LoxValue) {
+        self.stack.push(Activation {
+            key: Profile::key(func),
+            start: Instant::now(),
+            children: Duration::ZERO,
+        });
+    }
+
+    fn exit(&mut self) {
+        let path = self.stack_path();
+        let Some(activation) = self.stack.pop() else { return };
+        let elapsed = activation.start.elapsed();
+        let exclusive = elapsed.saturating_sub(activation.children);
+
+        // Recursive activations are already covered by the outermost one.
+        let recursive = self.stack.iter().any(|a| a.key == activation.key);
+        let stats = self.functions.entry(activation.key).or_default();
+        stats.calls += 1;
+        stats.exclusive += exclusive;
+        if !recursive {
+            stats.inclusive += elapsed;
+        }
+
+        *self.folded.entry(path).or_default() += exclusive;
+        match self.stack.last_mut() {
+            Some(parent) => parent.children += elapsed,
+            None => self.in_functions += elapsed,
+        }
+    }
+
+    /// Stops the clock, attributing time outside any function to the
+    /// top-level script.
+    pub fn finish(&mut self) {
+        while !self.stack.is_empty() {
+            self.exit();
+        }
+        self.total = self.start.elapsed();
+        self.folded.insert(SCRIPT.to_string(), self.total.saturating_sub(self.in_functions));
+    }
+
+    /// A table of the profiled functions, most expensive first.
+    pub fn summary(&self) -> String {
+        let mut rows: Vec<_> = self.functions.iter().collect();
+        rows.sort_by(|a, b| b.1.exclusive.cmp(&a.1.exclusive).then(a.0.cmp(b.0)));
+
+        let mut out = format!(
+            "total time: {:.3} ms\n{:<30} {:>10} {:>15} {:>15}\n",
+            ms(self.total),
+            "function",
+            "calls",
+            "inclusive (ms)",
+            "exclusive (ms)",
+        );
+        for (key, stats) in rows {
+            out.push_str(&format!(
+                "{:<30} {:>10} {:>15.3} {:>15.3}\n",
+                key,
+                stats.calls,
+                ms(stats.inclusive),
+                ms(stats.exclusive),
+            ));
+        }
+        out
+    }
+
+    /// Folded stacks with exclusive microseconds per stack, the input
+    /// format of flamegraph.pl and inferno.
+    pub fn folded(&self) -> String {
+        let mut lines: Vec<_> = self.folded.iter()
+            .map(|(path, d)| format!("{} {}\n", path, d.as_micros()))
+            .collect();
+        lines.sort();
+        lines.concat()
+    }
+}
+
+
+fn ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+
+pub struct Profiler(Rc<RefCell<Profile>>);
+
+impl Profiler {
+    pub fn new(profile: &Rc<RefCell<Profile>>) -> Profiler {
+        Profiler(profile.clone())
+    }
+}
+
+impl Hook for Profiler {
+    fn enter_call(&mut self, func: &LoxValue, _args: &[LoxValue]) -> Result<(), String> {
+        self.0.borrow_mut().enter(func);
+        Ok(())
+    }
+
+    fn exit_call(&mut self, _func: &LoxValue, _result: Option<&LoxValue>) {
+        self.0.borrow_mut().exit();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hooks;
+    use crate::interpreter::Interpreter;
+    use crate::source::Source;
+
+    fn profile(code: &str) -> Rc<RefCell<Profile>> {
+        let profile = Profile::new();
+        hooks::install(Box::new(Profiler::new(&profile)));
+        Interpreter::new().interpret(&mut Source::from_string(code.to_string())).unwrap();
+        hooks::clear();
+        profile.borrow_mut().finish();
+        profile
+    }
+
+    #[test]
+    fn test_call_counts() {
+        let profile = profile("
+fun fact(n) {
+    if n == 1 {
+        return 1;
+    }
+    return n * fact(n - 1);
+}
+fun twice() { fact(3); fact(4); }
+twice();
+");
+        let profile = profile.borrow();
+        assert_eq!(profile.functions["fact:2"].calls, 7);
+        assert_eq!(profile.functions["twice:8"].calls, 1);
+        assert!(profile.functions["twice:8"].inclusive >= profile.functions["fact:2"].inclusive);
+    }
+
+    #[test]
+    fn test_definition_positions() {
+        let profile = profile("
+fun f()
+{
+    return 1;
+}
+var a = () => 1; var b = fun() {
+    return 2;
+};
+f(); a(); b(); b();
+");
+        let profile = profile.borrow();
+        let mut keys: Vec<(&str, usize)> = profile.functions.iter()
+            .map(|(key, stats)| (key.as_str(), stats.calls))
+            .collect();
+        keys.sort();
+        assert_eq!(keys, vec![("f:2", 1), ("lambda:6:26", 2), ("lambda:6:9", 1)]);
+    }
+
+    #[test]
+    fn test_folded_stacks() {
+        let profile = profile("
+fun inner() { }
+fun outer() { inner(); inner(); }
+outer();
+inner();
+");
+        let folded = profile.borrow().folded();
+        let stacks: Vec<&str> = folded.lines()
+            .map(|l| l.rsplit_once(' ').unwrap().0)
+            .collect();
+        assert_eq!(stacks, vec![
+            "<script>",
+            "<script>;inner:2",
+            "<script>;outer:3",
+            "<script>;outer:3;inner:2",
+        ]);
+    }
+}
@@ -0,0 +1,508 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Instant;
+
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::{CmdKind, Highlighter};
+use rustyline::hint::Hinter;
+use rustyline::history::FileHistory;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Context, Editor, Helper, Result as RLResult};
+
+use crate::environment::Environment;
+use crate::interpreter::{InterpretError, Interpreter};
+use crate::parser::{parse_dialect, Dialect};
+use crate::source::Source;
+use crate::tokenizer::{tokenize, TokenType, KEYWORDS};
+
+
+const PROMPT: &str = "bwl >";
+const CONTINUATION_PROMPT: &str = "... >";
+
+const HELP: &str = "\
+:help              show this message
+:env               list global variables with their types
+:tokens <code>     show the tokens of <code>
+:ast <code>        show the syntax tree of <code>
+:load <file>       run a file in the current session
+:reset             forget all variables and functions
+:time <code>       run <code> and report how long it took
+:save <file>       write the inputs that ran successfully to <file>";
+
+const RESET: &str = "\x1b[0m";
+const KEYWORD: &str = "\x1b[1;35m";
+const STRING: &str = "\x1b[32m";
+const NUMBER: &str = "\x1b[36m";
+
+
+/// Whether `code` is the start of a statement that more lines could
+/// complete: it has unclosed brackets, an unterminated string, or fails to
+/// parse as `dialect` only because it ends too soon.
+pub fn is_incomplete(code: &str, dialect: Dialect) -> bool {
+    let src = Source::from_string(code.to_string());
+    let tokens = match tokenize(&src) {
+        Ok(tokens) => tokens,
+        Err(e) => return e.at_eof(),
+    };
+
+    let mut depth = 0;
+    for token in tokens.iter() {
+        match token.get_type() {
+            TokenType::LeftBrace | TokenType::LeftParen => depth += 1,
+            TokenType::RightBrace | TokenType::RightParen => depth -= 1,
+            _ => (),
+        }
+    }
+    if depth > 0 {
+        return true;
+    }
+
+    match parse_dialect(&tokens, dialect) {
+        Ok(_) => false,
+        Err(e) => e.at_eof(),
+    }
+}
+
+
+/// The first closing bracket in `code` that does not match the bracket
+/// it closes, as an error message.
+pub fn mismatched_bracket(code: &str) -> Option<String> {
+    use TokenType::*;
+    let src = Source::from_string(code.to_string());
+    let tokens = tokenize(&src).ok()?;
+
+    let mut open = Vec::new();
+    for token in tokens.iter() {
+        let opener = match token.get_type() {
+            LeftBrace | LeftParen => {
+                open.push(*token.get_type());
+                continue;
+            },
+            RightBrace => LeftBrace,
+            RightParen => LeftParen,
+            _ => continue,
+        };
+        if open.pop() != Some(opener) {
+            let pos = token.get_position();
+            return Some(format!(
+                "unmatched '{}' at line {}, column {}",
+                token.lexeme,
+                pos.lineno,
+                pos.linepos,
+            ));
+        }
+    }
+    None
+}
+
+
+/// `line` with keywords and literals coloured, or unchanged if it does not
+/// tokenize.
+pub fn highlight(line: &str) -> Cow<'_, str> {
+    let src = Source::from_string(line.to_string());
+    let Ok(tokens) = tokenize(&src) else { return Cow::Borrowed(line) };
+
+    // Token positions are 1-based (line, column) in chars.
+    let mut line_starts = vec![0];
+    for (idx, ch) in line.char_indices() {
+        if ch == '\n' {
+            line_starts.push(idx + 1);
+        }
+    }
+
+    let mut out = String::with_capacity(line.len());
+    let mut last = 0;
+    for token in tokens.iter() {
+        let colour = match token.get_type() {
+            TokenType::Str | TokenType::Interpolation => STRING,
+            TokenType::Number => NUMBER,
+            typ if typ.is_keyword() => KEYWORD,
+            _ => continue,
+        };
+        let pos = token.get_position();
+        let line_start = line_starts[pos.lineno - 1];
+        let start = line_start + line[line_start..].chars()
+            .take(pos.linepos - 1)
+            .map(char::len_utf8)
+            .sum::<usize>();
+        let end = start + token.lexeme.len();
+
+        out.push_str(&line[last..start]);
+        out.push_str(colour);
+        out.push_str(&line[start..end]);
+        out.push_str(RESET);
+        last = end;
+    }
+    out.push_str(&line[last..]);
+    Cow::Owned(out)
+}
+
+
+/// Completion, highlighting and bracket validation for the REPL.
+pub struct LoxHelper {
+    env: Rc<Environment>,
+    // Lines already entered for the statement being typed.
+    pending: RefCell<String>,
+}
+
+impl LoxHelper {
+    pub fn new(env: &Rc<Environment>) -> LoxHelper {
+        LoxHelper {
+            env: env.clone(),
+            pending: RefCell::new(String::new()),
+        }
+    }
+
+    /// Keywords and global names starting with `prefix`, sorted.
+    pub fn candidates(&self, prefix: &str) -> Vec<String> {
+        let mut names: Vec<String> = KEYWORDS.iter()
+            .filter_map(|k| k.lexeme())
+            .map(|k| k.to_string())
+            .chain(self.env.bindings().into_iter().map(|(name, _)| name))
+            .filter(|name| name.starts_with(prefix))
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+impl Completer for LoxHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> RLResult<(usize, Vec<Pair>)> {
+        let start = line[..pos]
+            .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .map(|idx| idx + 1)
+            .unwrap_or(0);
+        let pairs = self.candidates(&line[start..pos]).into_iter()
+            .map(|name| Pair { display: name.clone(), replacement: name })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for LoxHelper {
+    type Hint = String;
+}
+
+impl Highlighter for LoxHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        highlight(line)
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize, _kind: CmdKind) -> bool {
+        true
+    }
+}
+
+impl Validator for LoxHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> RLResult<ValidationResult> {
+        let code = format!("{}{}", self.pending.borrow(), ctx.input());
+        Ok(match mismatched_bracket(&code) {
+            Some(msg) => ValidationResult::Invalid(Some(format!("  <- {}", msg))),
+            None => ValidationResult::Valid(None),
+        })
+    }
+}
+
+impl Helper for LoxHelper {}
+
+
+/// An interpreter plus the inputs it has run successfully, for `:save`.
+pub struct Session {
+    pub interpreter: Interpreter,
+    inputs: Vec<String>,
+}
+
+impl Session {
+    pub fn new(interpreter: Interpreter) -> Session {
+        Session {
+            interpreter,
+            inputs: Vec::new(),
+        }
+    }
+
+    pub fn eval(&mut self, code: &str) -> Result<Option<String>, InterpretError> {
+        let result = self.interpreter.interpret(&mut Source::from_string(code.to_string()))?;
+        self.inputs.push(code.trim_end().to_string());
+        Ok(result)
+    }
+
+    /// Runs a `:command`, returning the text to show for it.
+    pub fn command(&mut self, line: &str) -> Result<String, String> {
+        let line = line.trim().trim_start_matches(':');
+        let (name, arg) = match line.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, arg.trim()),
+            None => (line, ""),
+        };
+        let needs_arg = |what: &str| -> Result<(), String> {
+            match arg {
+                "" => Err(format!(":{} needs {}", name, what)),
+                _ => Ok(()),
+            }
+        };
+
+        match name {
+            "help" | "h" => Ok(HELP.to_string()),
+            "env" => {
+                let lines: Vec<String> = self.interpreter.env().script_bindings().into_iter()
+                    .map(|(name, value)| match value {
+                        Some(v) => format!("{}: {} = {}", name, *v, v.value_string()),
+                        None => format!("{}: uninitialized", name),
+                    })
+                    .collect();
+                Ok(lines.join("\n"))
+            },
+            "tokens" => {
+                needs_arg("some code")?;
+                let src = Source::from_string(arg.to_string());
+                let tokens = tokenize(&src).map_err(|e| src.format_error(&e))?;
+                let lines: Vec<String> = tokens.iter().map(|t| t.to_string()).collect();
+                Ok(lines.join("\n"))
+            },
+            "ast" => {
+                needs_arg("some code")?;
+                let src = Source::from_string(arg.to_string());
+                let tokens = tokenize(&src).map_err(|e| src.format_error(&e))?;
+                let ast = parse_dialect(&tokens, self.interpreter.dialect())
+                    .map_err(|e| src.format_error(&e))?;
+                Ok(ast.to_string().trim_end().to_string())
+            },
+            "load" => {
+                needs_arg("a file name")?;
+                let mut src = Source::from_file(arg)?;
+                let result = self.interpreter.interpret(&mut src).map_err(|e| e.to_string())?;
+                self.inputs.push(src.content.trim_end().to_string());
+                Ok(result.unwrap_or_default())
+            },
+            "reset" => {
+                self.interpreter.reset();
+                self.inputs.clear();
+                Ok("environment reset".to_string())
+            },
+            "time" => {
+                needs_arg("some code")?;
+                let start = Instant::now();
+                let result = self.eval(arg);
+                let elapsed = format!("time: {:.3} ms", start.elapsed().as_secs_f64() * 1000.0);
+                match result {
+                    Ok(Some(v)) => Ok(format!("{}\n{}", v, elapsed)),
+                    Ok(None) => Ok(elapsed),
+                    Err(e) => Err(format!("{}\n{}", e, elapsed)),
+                }
+            },
+            "save" => {
+                needs_arg("a file name")?;
+                let code: String = self.inputs.iter().map(|input| format!("{}\n", input)).collect();
+                fs::write(arg, code)
+                    .map_err(|e| format!("failed to write '{}': {}", arg, e))?;
+                Ok(format!("saved {} input(s) to {}", self.inputs.len(), arg))
+            },
+            _ => Err(format!("unknown command ':{}', try :help", name)),
+        }
+    }
+}
+
+
+fn history_path() -> Option<PathBuf> {
+    let dir = dirs::data_dir()?.join("bagelwithlox");
+    std::fs::create_dir_all(&dir).ok()?;
+    Some(dir.join("history.txt"))
+}
+
+
+pub fn run(session: &mut Session) -> RLResult<()> {
+    let mut rl: Editor<LoxHelper, FileHistory> = Editor::new()?;
+    rl.set_helper(Some(LoxHelper::new(session.interpreter.env())));
+    let history = history_path();
+    if let Some(path) = &history {
+        // There is no history file before the first session.
+        let _ = rl.load_history(path);
+    }
+    let mut buffer = String::new();
+
+    loop {
+        let prompt = if buffer.is_empty() { PROMPT } else { CONTINUATION_PROMPT };
+        match rl.readline(prompt) {
+            Ok(line) => {
+                if buffer.is_empty() && line.trim() == "" { continue; }
+                rl.add_history_entry(line.as_str())?;
+
+                if buffer.is_empty() && line.trim_start().starts_with(':') {
+                    match session.command(&line) {
+                        Ok(text) if text.is_empty() => (),
+                        Ok(text) => println!("{}", text),
+                        Err(e) => eprintln!("{}", e),
+                    }
+                    // :reset and :load replace or add globals to complete
+                    rl.helper_mut().unwrap().env = session.interpreter.env().clone();
+                    continue;
+                }
+
+                // A blank continuation line runs the input as it is, so
+                // there is a way out of a statement that cannot be finished.
+                let blank = line.trim() == "";
+                buffer.push_str(&line);
+                buffer.push('\n');
+                if !blank && is_incomplete(&buffer, session.interpreter.dialect()) {
+                    rl.helper().unwrap().pending.replace(buffer.clone());
+                    continue;
+                }
+
+                let code = std::mem::take(&mut buffer);
+                rl.helper().unwrap().pending.borrow_mut().clear();
+                match session.eval(&code) {
+                    Ok(Some(result)) => println!("{}", result),
+                    Ok(None) => (),
+                    Err(e) => eprintln!("{}", e),
+                }
+            },
+            Err(ReadlineError::Interrupted) => {
+                if !buffer.is_empty() {
+                    buffer.clear();
+                    rl.helper().unwrap().pending.borrow_mut().clear();
+                    println!("CTRL-C: input cancelled");
+                }
+            },
+            Err(ReadlineError::Eof) => {
+                println!("CTRL-D");
+                break
+            },
+            Err(err) => {
+                println!("Error: {:?}", err);
+                break
+            }
+        }
+    }
+
+    if let Some(path) = &history {
+        rl.save_history(path)?;
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::Backend;
+    use crate::optimizer::Passes;
+
+    #[test]
+    fn test_is_incomplete() {
+        for code in [
+            "fun f() {",
+            "fun f() {\n  print 1;\n",
+            "print (1 +",
+            "print \"abc",
+            "print 1",
+            "var x =",
+            "if true {\n} else",
+        ] {
+            assert!(is_incomplete(code, Dialect::Bwl), "{:?} should be incomplete", code);
+        }
+
+        for code in [
+            "",
+            "print 1;",
+            "fun f() {\n  print 1;\n}",
+            "print 1 + ;",
+            "print 1; }",
+            "var = 1;",
+        ] {
+            assert!(!is_incomplete(code, Dialect::Bwl), "{:?} should be complete", code);
+        }
+
+        assert!(is_incomplete("if (true)", Dialect::Classic));
+        assert!(!is_incomplete("if (true) print 1;", Dialect::Classic));
+    }
+
+    #[test]
+    fn test_mismatched_bracket() {
+        assert_eq!(mismatched_bracket("fun f() {\n  print (1 + 2);\n}"), None);
+        assert_eq!(mismatched_bracket("fun f() {"), None);
+        assert_eq!(
+            mismatched_bracket("print (1 + 2};"),
+            Some("unmatched '}' at line 1, column 13".to_string()),
+        );
+        assert_eq!(
+            mismatched_bracket("{\n})"),
+            Some("unmatched ')' at line 2, column 2".to_string()),
+        );
+    }
+
+    #[test]
+    fn test_highlight() {
+        assert_eq!(
+            highlight("var s = \"é\" + 12;"),
+            format!("{}var{} s = {}\"é\"{} + {}12{};", KEYWORD, RESET, STRING, RESET, NUMBER, RESET),
+        );
+        assert_eq!(highlight("print \"abc"), "print \"abc");
+    }
+
+    #[test]
+    fn test_commands() {
+        let mut session = Session::new(Interpreter::new());
+        session.eval("var x = 1;\nfun f() {}\n").unwrap();
+        assert!(session.eval("print y;\n").is_err());
+        session.eval("var s = \"a\";\n").unwrap();
+
+        assert_eq!(
+            session.command(":env"),
+            Ok("f: Callable = f\ns: String = a\nx: Int = 1".to_string()),
+        );
+        assert_eq!(
+            session.command(":tokens print 1;"),
+            Ok("   1:1    Print        \"print\"\n   1:7    Number       \"1\"\n   1:8    SemiColon    \";\"".to_string()),
+        );
+        assert_eq!(session.command(":ast while x {}"), Ok("while var x\n  block".to_string()));
+        assert!(session.command(":time return x + 1;").unwrap().starts_with("2\ntime: "));
+        assert_eq!(session.command(":tokens"), Err(":tokens needs some code".to_string()));
+        assert!(session.command(":nope").is_err());
+
+        let path = std::env::temp_dir().join("bwl_repl_session.lox");
+        let path = path.to_str().unwrap();
+        session.command(&format!(":save {}", path)).unwrap();
+        assert_eq!(
+            fs::read_to_string(path).unwrap(),
+            "var x = 1;\nfun f() {}\nvar s = \"a\";\nreturn x + 1;\n",
+        );
+
+        session.command(":reset").unwrap();
+        assert_eq!(session.command(":env"), Ok("".to_string()));
+        session.command(&format!(":load {}", path)).unwrap();
+        assert_eq!(session.eval("return s;"), Ok(Some("a".to_string())));
+    }
+
+    #[test]
+    fn test_reset_keeps_settings() {
+        let mut interpreter = Interpreter::new()
+            .with_dialect(Dialect::Classic)
+            .with_passes(Passes::all())
+            .with_backend(Backend::Closures);
+        interpreter.set_args(vec!["first".to_string()]);
+        let mut session = Session::new(interpreter);
+        session.eval("var x = 1;").unwrap();
+
+        session.command(":reset").unwrap();
+        assert!(session.eval("return x;").is_err());
+        assert_eq!(session.interpreter.dialect(), Dialect::Classic);
+        assert_eq!(session.interpreter.passes(), Passes::all());
+        assert_eq!(session.interpreter.backend(), Backend::Closures);
+        assert_eq!(session.eval("return arg(0);"), Ok(Some("first".to_string())));
+    }
+
+    #[test]
+    fn test_candidates() {
+        let mut interpreter = Interpreter::new();
+        interpreter.interpret(&mut Source::from_string("var value = 1; fun vector() {}".to_string())).unwrap();
+        let helper = LoxHelper::new(interpreter.env());
+        assert_eq!(helper.candidates("v"), vec!["value", "var", "vector"]);
+        assert_eq!(helper.candidates("wh"), vec!["while"]);
+    }
+}
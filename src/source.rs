@@ -1,5 +1,6 @@
 use std::fs;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 
 pub trait SourceError {
@@ -14,6 +15,8 @@ pub struct FilePosition {
     pub lineno: usize,
     pub linepos: usize,
     pub length: usize,
+    /// The `id` of the `Source` the position is in.
+    pub source: usize,
 }
 
 impl FilePosition {
@@ -22,6 +25,7 @@ impl FilePosition {
             lineno,
             linepos,
             length: 0,
+            source: 0,
         }
     }
 
@@ -30,6 +34,7 @@ impl FilePosition {
             lineno,
             linepos,
             length,
+            source: 0,
         }
     }
 
@@ -44,16 +49,22 @@ impl FilePosition {
 }
 
 
+// Sources read from strings share id 0; each file gets its own id.
+static NEXT_SOURCE_ID: AtomicUsize = AtomicUsize::new(1);
+
+
 pub struct Source {
     pub filename: String,
     pub content: Rc<String>,
+    pub id: usize,
 }
 
 impl Source {
-    fn new(filename: String, content: String) -> Source {
+    fn new(filename: String, content: String, id: usize) -> Source {
         Source{
             filename,
             content: Rc::new(content),
+            id,
         }
     }
 
@@ -61,6 +72,7 @@ impl Source {
         Source::new(
             "__str__".to_string(),
             content,
+            0,
         )
     }
 
@@ -69,6 +81,7 @@ impl Source {
             Ok(content) =>  Ok(Source::new(
                 path.to_string(),
                 content,
+                NEXT_SOURCE_ID.fetch_add(1, Ordering::Relaxed),
             )),
             Err(e) => Err(format!("Failed to read file '{}': {}", path, e)),
         }
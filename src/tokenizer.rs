@@ -1,4 +1,6 @@
+use std::borrow::Cow;
 use std::fmt;
+use crate::bigint::BigInt;
 use crate::source::{
     FilePosition,
     Source,
@@ -16,6 +18,7 @@ const TOKENIZE_ERROR: &'static str = "TokenizeError";
 pub struct TokenizeError {
     pos: Option<FilePosition>,
     msg: String,
+    eof: bool,
 }
 
 impl SourceError for TokenizeError {
@@ -37,15 +40,35 @@ impl TokenizeError {
         TokenizeError {
             pos: Some(pos),
             msg,
+            eof: false,
         }
     }
+
+    fn eof(pos: FilePosition, msg: String) -> TokenizeError {
+        TokenizeError {
+            pos: Some(pos),
+            msg,
+            eof: true,
+        }
+    }
+
+    /// Whether the source ended in the middle of a token, like an
+    /// unterminated string.
+    pub fn at_eof(&self) -> bool {
+        self.eof
+    }
 }
 
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum LiteralValue<'a> {
+    LInt(i64),
+    /// An int literal too large for `LInt`.
+    LBigInt(BigInt),
     LNumber(f64),
-    LString(&'a str),
+    /// The string's value, borrowed from the source unless it contains
+    /// escape sequences.
+    LString(Cow<'a, str>),
 }
 
 
@@ -123,6 +146,22 @@ impl<'a> Token<'a> {
 }
 
 
+/// The token's position, type and lexeme, aligned for listing one token
+/// per line.
+impl<'a> fmt::Display for Token<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:>4}:{:<4} {:<12} {:?}",
+            self.pos.lineno,
+            self.pos.linepos,
+            self.typ.to_string(),
+            self.lexeme,
+        )
+    }
+}
+
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum TokenType {
     // Single-character tokens.
@@ -131,27 +170,36 @@ pub enum TokenType {
     LeftBrace,
     RightBrace,
     Comma,
+    Colon,
     Dot,
     Minus,
     Plus,
     SemiColon,
     Star,
+    Percent,
 
     // One Or Two Character Tokens.
     Bang,
     BangEqual,
     Equal,
     EqualEqual,
+    Arrow,
+    Ellipsis,
     Greater,
     GreaterEqual,
     Less,
     LessEqual,
     Slash,
     Comment,
+    /// A `///` comment documenting the declaration after it.
+    DocComment,
 
     // Literals.
     Identifier,
     Str,
+    /// The part of a string literal before an interpolated `${...}`
+    /// expression, up to and including the `${`.
+    Interpolation,
     Number,
 
     // Keywords.
@@ -187,7 +235,17 @@ impl fmt::Display for TokenType {
     }
 }
 
+pub const KEYWORDS: [TokenType; 16] = {
+    use TokenType::*;
+    [And, Class, Else, False, Fun, For, If, Nil, Or, Print, Return, Super, This, True, Var, While]
+};
+
+
 impl TokenType {
+    pub fn is_keyword(&self) -> bool {
+        KEYWORDS.contains(self)
+    }
+
     pub fn lexeme(&self) -> Option<&'static str> {
         use TokenType::*;
         match self {
@@ -196,15 +254,19 @@ impl TokenType {
             LeftBrace => Some("{"),
             RightBrace => Some("}"),
             Comma => Some(","),
+            Colon => Some(":"),
             Dot => Some("."),
             Minus => Some("-"),
             Plus => Some("+"),
             SemiColon => Some(";"),
             Star => Some("*"),
+            Percent => Some("%"),
             Bang => Some("!"),
             BangEqual => Some("!="),
             Equal => Some("="),
             EqualEqual => Some("=="),
+            Arrow => Some("=>"),
+            Ellipsis => Some("..."),
             Greater => Some(">"),
             GreaterEqual => Some(">="),
             Less => Some("<"),
@@ -236,36 +298,349 @@ impl TokenType {
 pub type Tokens<'a> = Vec<Token<'a>>;
 
 
-fn find_number_end(token_iter: &mut TokenIter) -> usize {
-    let mut end = 0;
-    let mut has_dot = false;
+// Consumes a block comment after its opening `/*` at `open`, including
+// any comments nested in it.
+fn skip_block_comment(token_iter: &mut TokenIter, open: FilePosition) -> Result<(), TokenizeError> {
+    let mut depth = 1;
+    while depth > 0 {
+        match token_iter.next() {
+            Some((_, '/')) if token_iter.next_if_eq('*').is_some() => depth += 1,
+            Some((_, '*')) if token_iter.next_if_eq('/').is_some() => depth -= 1,
+            Some(_) => (),
+            None => return Err(TokenizeError::eof(open, "unterminated block comment".to_string())),
+        }
+    }
+    Ok(())
+}
+
+
+// The position of the next character, which must be on the current line.
+fn next_pos(token_iter: &TokenIter) -> FilePosition {
+    let mut pos = token_iter.filepos;
+    pos.linepos += 1;
+    pos.length = 1;
+    pos
+}
+
 
-    while let Some((_, ch)) = token_iter.peek() {
-        match ch {
-            '.' => {
-                if !has_dot {
-                    end += 1;
-                    has_dot = true;
-                    token_iter.next();
-                } else {
-                    break;
+// Consumes digits in `radix`, which may be separated by single `_`s,
+// returning the digits. `after_digit` is whether a digit came just before.
+fn scan_digits(
+    token_iter: &mut TokenIter,
+    radix: u32,
+    mut after_digit: bool,
+) -> Result<String, TokenizeError> {
+    let mut digits = String::new();
+    while let Some(&(_, ch)) = token_iter.peek() {
+        if ch.is_digit(radix) {
+            digits.push(ch);
+            after_digit = true;
+        } else if ch == '_' {
+            let pos = next_pos(token_iter);
+            token_iter.next();
+            let before_digit = token_iter.peek().is_some_and(|(_, ch)| ch.is_digit(radix));
+            if !after_digit || !before_digit {
+                return Err(TokenizeError::new(
+                    pos,
+                    "'_' in a number literal must be between digits".to_string(),
+                ));
+            }
+            after_digit = false;
+            continue;
+        } else {
+            break;
+        }
+        token_iter.next();
+    }
+    Ok(digits)
+}
+
+
+// Fails if a number literal runs straight into a letter or digit that
+// cannot be part of it.
+fn check_number_end(token_iter: &mut TokenIter, kind: &str) -> Result<(), TokenizeError> {
+    match token_iter.peek() {
+        Some(&(_, ch)) if ch.is_alphanumeric() || ch == '_' => Err(TokenizeError::new(
+            next_pos(token_iter),
+            format!("invalid character '{}' in {} literal", ch, kind),
+        )),
+        _ => Ok(()),
+    }
+}
+
+
+// The value of the valid digits of an int literal.
+fn int_literal<'a>(digits: &str, radix: u32) -> LiteralValue<'a> {
+    match i64::from_str_radix(digits, radix) {
+        Ok(value) => LiteralValue::LInt(value),
+        Err(_) => LiteralValue::LBigInt(BigInt::parse_radix(digits, radix).unwrap()),
+    }
+}
+
+
+// Consumes the rest of a number literal starting with the digit
+// `first`, returning the index after its last character and its value:
+// an int unless it has a decimal point or exponent.
+fn scan_number<'a>(
+    token_iter: &mut TokenIter,
+    src: &Source,
+    first: char,
+) -> Result<(usize, LiteralValue<'a>), TokenizeError> {
+    let prefixed = match (first, token_iter.peek()) {
+        ('0', Some((_, 'x' | 'X'))) => Some((16, "hexadecimal")),
+        ('0', Some((_, 'o' | 'O'))) => Some((8, "octal")),
+        ('0', Some((_, 'b' | 'B'))) => Some((2, "binary")),
+        _ => None,
+    };
+
+    let value = if let Some((radix, kind)) = prefixed {
+        token_iter.next();
+        let digits = scan_digits(token_iter, radix, false)?;
+        if digits.is_empty() {
+            return Err(TokenizeError::new(
+                next_pos(token_iter),
+                format!("expected digits in {} literal", kind),
+            ));
+        }
+        check_number_end(token_iter, kind)?;
+        int_literal(&digits, radix)
+    } else {
+        let mut text = first.to_string();
+        text.push_str(&scan_digits(token_iter, 10, true)?);
+        let mut float = false;
+
+        // only a dot followed by a digit is a decimal point; `1.` could be
+        // followed by a method name
+        if let Some(&(idx, '.')) = token_iter.peek() {
+            if src.content[idx + 1..].starts_with(|ch: char| ch.is_ascii_digit()) {
+                token_iter.next();
+                float = true;
+                text.push('.');
+                text.push_str(&scan_digits(token_iter, 10, false)?);
+            }
+        }
+
+        if token_iter.next_if(|&(_, ch)| ch == 'e' || ch == 'E').is_some() {
+            float = true;
+            text.push('e');
+            if let Some((_, sign)) = token_iter.next_if(|&(_, ch)| ch == '+' || ch == '-') {
+                text.push(sign);
+            }
+            let digits = scan_digits(token_iter, 10, false)?;
+            if digits.is_empty() {
+                return Err(TokenizeError::new(
+                    next_pos(token_iter),
+                    "expected digits in exponent".to_string(),
+                ));
+            }
+            text.push_str(&digits);
+        }
+
+        check_number_end(token_iter, "number")?;
+        match float {
+            true => LiteralValue::LNumber(text.parse().unwrap()),
+            false => int_literal(&text, 10),
+        }
+    };
+
+    Ok((token_iter.next_index().unwrap_or(src.content.len()), value))
+}
+
+
+// Consumes a string literal, or the rest of one after an interpolated
+// expression, from the character after `start`. Returns `Interpolation`
+// if it stopped at a `${`, and the index of its last character and the
+// value with escapes replaced.
+fn scan_string<'a>(
+    token_iter: &mut TokenIter,
+    src: &'a Source,
+    start: usize,
+) -> Result<(TokenType, usize, Cow<'a, str>), TokenizeError> {
+    let mut value = String::new();
+    let mut escaped = false;
+
+    loop {
+        let Some((idx, ch)) = token_iter.next() else {
+            return Err(TokenizeError::eof(
+                token_iter.filepos,
+                "unterminated string literal".to_string(),
+            ));
+        };
+        let typ = match ch {
+            '"' => TokenType::Str,
+            '$' if token_iter.next_if_eq('{').is_some() => TokenType::Interpolation,
+            '\\' => {
+                if !escaped {
+                    value.push_str(&src.content[start + 1..idx]);
+                    escaped = true;
                 }
+                value.push(scan_escape(token_iter)?);
+                continue;
             },
-            _ if ch.is_digit(10) => {
-                end += 1;
-                token_iter.next();
+            _ => {
+                if escaped {
+                    value.push(ch);
+                }
+                continue;
             },
-            _ => {break;},
+        };
+        let end = match typ {
+            TokenType::Interpolation => idx + 1,
+            _ => idx,
+        };
+        return Ok(match escaped {
+            true => (typ, end, Cow::Owned(value)),
+            false => (typ, end, Cow::Borrowed(&src.content[start + 1..idx])),
+        });
+    }
+}
+
+
+// Consumes the rest of a raw string after its `r"`, which is taken as
+// written up to the next `"`.
+fn scan_raw_string<'a>(
+    token_iter: &mut TokenIter,
+    src: &'a Source,
+    start: usize,
+) -> Result<(usize, &'a str), TokenizeError> {
+    for (idx, ch) in token_iter.by_ref() {
+        if ch == '"' {
+            return Ok((idx, &src.content[start + 2..idx]));
+        }
+    }
+    Err(TokenizeError::eof(
+        token_iter.filepos,
+        "unterminated raw string literal".to_string(),
+    ))
+}
+
+
+// Consumes the rest of a string after its opening `"""`, up to the next
+// `"""`, returning the index of its last character and its dedented text.
+fn scan_triple_string(
+    token_iter: &mut TokenIter,
+    src: &Source,
+    start: usize,
+) -> Result<(usize, String), TokenizeError> {
+    let mut quotes = 0;
+    for (idx, ch) in token_iter.by_ref() {
+        quotes = if ch == '"' { quotes + 1 } else { 0 };
+        if quotes == 3 {
+            return Ok((idx, dedent(&src.content[start + 3..idx - 2])));
         }
     }
-    end
+    Err(TokenizeError::eof(
+        token_iter.filepos,
+        "unterminated multi-line string literal".to_string(),
+    ))
+}
+
+
+// The text of a `"""` string. If it starts on a new line, that line break,
+// the line break and indentation before the closing quotes, and the
+// indentation common to every other non-blank line are removed.
+fn dedent(text: &str) -> String {
+    let Some(text) = text.strip_prefix('\n').or_else(|| text.strip_prefix("\r\n")) else {
+        return text.to_string();
+    };
+    let mut lines: Vec<&str> = text.split('\n').collect();
+    if lines.len() > 1 && lines.last().is_some_and(|l| l.trim().is_empty()) {
+        lines.pop();
+    }
+
+    let indent = lines.iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.len() - l.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    lines.iter()
+        .map(|l| l.get(indent..).unwrap_or("").trim_end_matches('\r'))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+
+// Consumes an escape sequence after its backslash.
+fn scan_escape(token_iter: &mut TokenIter) -> Result<char, TokenizeError> {
+    let mut pos = token_iter.filepos;
+    pos.length = 2;
+
+    let Some((_, ch)) = token_iter.next() else {
+        return Err(TokenizeError::eof(
+            token_iter.filepos,
+            "unterminated string literal".to_string(),
+        ));
+    };
+    match ch {
+        'n' => Ok('\n'),
+        't' => Ok('\t'),
+        'r' => Ok('\r'),
+        '0' => Ok('\0'),
+        '\\' => Ok('\\'),
+        '"' => Ok('"'),
+        '$' => Ok('$'),
+        'u' => {
+            if token_iter.next_if_eq('{').is_none() {
+                return Err(TokenizeError::new(
+                    pos,
+                    "expected '{' after \\u in unicode escape".to_string(),
+                ));
+            }
+            let mut digits = String::new();
+            while let Some((_, digit)) = token_iter.next_if(|&(_, ch)| ch.is_ascii_hexdigit()) {
+                digits.push(digit);
+            }
+            pos.length += 1 + digits.len();
+            if digits.is_empty() || digits.len() > 6 || token_iter.next_if_eq('}').is_none() {
+                return Err(TokenizeError::new(
+                    pos,
+                    "unicode escape must be \\u{...} with 1 to 6 hex digits".to_string(),
+                ));
+            }
+            pos.length += 1;
+            let code = u32::from_str_radix(&digits, 16).unwrap();
+            char::from_u32(code).ok_or_else(|| TokenizeError::new(
+                pos,
+                format!("invalid unicode code point U+{:X}", code),
+            ))
+        },
+        other => Err(TokenizeError::new(
+            pos,
+            format!("invalid escape sequence: \\{}", other),
+        )),
+    }
+}
+
+
+fn string_token<'a>(
+    token_iter: &mut TokenIter,
+    src: &'a Source,
+    start: usize,
+    pos: FilePosition,
+    interpolations: &mut Vec<usize>,
+) -> Result<Token<'a>, TokenizeError> {
+    let (typ, end, value) = scan_string(token_iter, src, start)?;
+    if typ == TokenType::Interpolation {
+        interpolations.push(0);
+    }
+    Ok(Token::new_literal(
+        typ,
+        pos,
+        &src.content[start..=end],
+        LiteralValue::LString(value),
+    ))
 }
 
+
 pub fn tokenize<'a>(src: &'a Source) -> Result<Tokens<'a>, TokenizeError> {
     use TokenType::*;
 
     let mut ch_idxs = TokenIter::new(src.content.char_indices().peekable());
+    ch_idxs.filepos.source = src.id;
     let mut tokens = Tokens::new();
+    // The number of unclosed braces in each interpolated expression we
+    // are inside of, innermost last.
+    let mut interpolations: Vec<usize> = Vec::new();
 
     while let Some((start, ch)) = ch_idxs.next() {
         let mut pos = ch_idxs.filepos;
@@ -277,14 +652,39 @@ pub fn tokenize<'a>(src: &'a Source) -> Result<Tokens<'a>, TokenizeError> {
             _ if ch.is_whitespace() => continue,
             '(' => Token::new(LeftParen, pos, "("),
             ')' => Token::new(RightParen, pos, ")"),
-            '{' => Token::new(LeftBrace, pos, "{"),
-            '}' => Token::new(RightBrace, pos, "}"),
+            '{' => {
+                if let Some(depth) = interpolations.last_mut() {
+                    *depth += 1;
+                }
+                Token::new(LeftBrace, pos, "{")
+            },
+            '}' => match interpolations.last_mut() {
+                Some(0) => {
+                    // the end of an interpolated expression, so the string
+                    // continues
+                    interpolations.pop();
+                    string_token(&mut ch_idxs, src, start, pos, &mut interpolations)?
+                },
+                Some(depth) => {
+                    *depth -= 1;
+                    Token::new(RightBrace, pos, "}")
+                },
+                None => Token::new(RightBrace, pos, "}"),
+            },
             ',' => Token::new(Comma, pos, ","),
+            ':' => Token::new(Colon, pos, ":"),
+            '.' if src.content[start..].starts_with("...") => {
+                ch_idxs.next();
+                ch_idxs.next();
+                pos.length = 3;
+                Token::new(Ellipsis, pos, "...")
+            },
             '.' => Token::new(Dot, pos, "."),
             '-' => Token::new(Minus, pos, "-"),
             '+' => Token::new(Plus, pos, "+"),
             ';' => Token::new(SemiColon, pos, ";"),
             '*' => Token::new(Star, pos, "*"),
+            '%' => Token::new(Percent, pos, "%"),
             '!' => match ch_idxs.next_if_eq('=') {
                 Some(_) => {
                     pos.length = 2;
@@ -292,11 +692,15 @@ pub fn tokenize<'a>(src: &'a Source) -> Result<Tokens<'a>, TokenizeError> {
                 },
                 None => Token::new(Bang, pos, "!"),
             },
-            '=' => match ch_idxs.next_if_eq('=') {
-                Some(_) => {
+            '=' => match ch_idxs.next_if(|&(_, ch)| ch == '=' || ch == '>') {
+                Some((_, '=')) => {
                     pos.length = 2;
                     Token::new(EqualEqual, pos, "==")
                 },
+                Some(_) => {
+                    pos.length = 2;
+                    Token::new(Arrow, pos, "=>")
+                },
                 None => Token::new(Equal, pos, "="),
             },
             '>' => match ch_idxs.next_if_eq('=') {
@@ -313,70 +717,70 @@ pub fn tokenize<'a>(src: &'a Source) -> Result<Tokens<'a>, TokenizeError> {
                 },
                 None => Token::new(Less, pos, "<"),
             },
-            '/' => match ch_idxs.next_if_eq('/') {
-                Some(_) => {
+            '/' => match ch_idxs.next_if(|&(_, ch)| ch == '/' || ch == '*') {
+                Some((_, '/')) => {
                     // we have a comment, and we'll consume
-                    // all content to the end of the line
-                    let mut end = start;
-                    while let Some((_end, _)) = ch_idxs.next_if_not_eq('\n') {
-                        end = ch_idxs.next_index().unwrap_or(_end);
-                    };
-                    pos.length += end - start;
-                    //Token::new(Comment, pos, &src.content[start..=end])
+                    // all content to the end of the line; only
+                    // `///` comments, not `////`, are kept as docs
+                    let doc = ch_idxs.next_if_eq('/').is_some()
+                        && ch_idxs.peek().is_none_or(|&(_, ch)| ch != '/');
+                    while ch_idxs.next_if_not_eq('\n').is_some() {}
+                    if !doc {
+                        continue;
+                    }
+
+                    let end = ch_idxs.next_index().unwrap_or(src.content.len());
+                    let lexeme = &src.content[start..end];
+                    pos.length = lexeme.chars().count();
+                    let text = &lexeme[3..];
+                    let text = text.strip_prefix(' ').unwrap_or(text).trim_end();
+                    Token::new_literal(DocComment, pos, lexeme, LiteralValue::LString(Cow::Borrowed(text)))
+                },
+                Some(_) => {
+                    pos.length = 2;
+                    skip_block_comment(&mut ch_idxs, pos)?;
                     continue;
                 },
-                None =>Token::new(Slash, pos, "/"),
+                None => Token::new(Slash, pos, "/"),
             },
 
             // String
-            '"' => {
-                let end: usize;
-
-                while let Some(_) = ch_idxs.next_if_not_eq('"') {}
-                match ch_idxs.next() {
-                    Some((_end, _)) => {
-                        // we know next is a "
-                        end = _end;
-                    },
-                    None => {
-                        // we got to the end without a "
-                        return Err(TokenizeError::new(
-                            ch_idxs.filepos,
-                            "unterminated string literal".to_string(),
-                        ));
-                    },
+            '"' if ch_idxs.next_if_eq('"').is_some() => {
+                if ch_idxs.next_if_eq('"').is_none() {
+                    // just an empty string
+                    Token::new_literal(Str, pos, "\"\"", LiteralValue::LString(Cow::Borrowed("")))
+                } else {
+                    let (end, value) = scan_triple_string(&mut ch_idxs, src, start)?;
+                    Token::new_literal(
+                        Str,
+                        pos,
+                        &src.content[start..=end],
+                        LiteralValue::LString(Cow::Owned(value)),
+                    )
                 }
-
+            },
+            '"' => string_token(&mut ch_idxs, src, start, pos, &mut interpolations)?,
+            'r' if ch_idxs.next_if_eq('"').is_some() => {
+                let (end, value) = scan_raw_string(&mut ch_idxs, src, start)?;
                 Token::new_literal(
                     Str,
                     pos,
                     &src.content[start..=end],
-                    LiteralValue::LString(&src.content[start+1..=end-1]),
-
+                    LiteralValue::LString(Cow::Borrowed(value)),
                 )
             },
 
             // Number
-            _ if ch.is_digit(10) => {
-                let end = start + find_number_end(&mut ch_idxs);
-                let lexeme = &src.content[start..=end];
-                pos.length += end - start;
-
-                let value = match lexeme.parse() {
-                    Ok(val) => val,
-                    Err(e) => {
-                        return Err(TokenizeError::new(
-                            pos,
-                            format!("invalid numeric literal: {}", e),
-                        ));
-                    },
-                };
+            _ if ch.is_ascii_digit() => {
+                let (end, value) = scan_number(&mut ch_idxs, src, ch)?;
+                let lexeme = &src.content[start..end];
+                pos.length = end - start;
 
                 Token::new_literal(
                     Number,
                     pos,
                     lexeme,
-                    LiteralValue::LNumber(value),
+                    value,
                 )
             },
 
@@ -407,6 +811,13 @@ pub fn tokenize<'a>(src: &'a Source) -> Result<Tokens<'a>, TokenizeError> {
 
     }
 
+    if !interpolations.is_empty() {
+        return Err(TokenizeError::eof(
+            ch_idxs.filepos,
+            "unterminated string interpolation".to_string(),
+        ));
+    }
+
     //let mut pos = ch_idxs.filepos;
     //pos.linepos += 1;
     //tokens.push(Token::new(Eof, pos));
@@ -523,13 +934,13 @@ mod tests {
                     Number,
                     FilePosition::nwl(1, 1, 1),
                     "1",
-                    LiteralValue::LNumber(1.0),
+                    LiteralValue::LInt(1),
                 ),
                 Token::new_literal(
                     Number,
                     FilePosition::nwl(1, 3, 4),
                     "1234",
-                    LiteralValue::LNumber(1234.0),
+                    LiteralValue::LInt(1234),
                 ),
                 Token::new_literal(
                     Number,
@@ -542,6 +953,49 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_extended_numbers() {
+        let tstr = "1e9 2.5E-3 1_000_000 0xff 0o17 0b1010 0XA_B 1.foo 3. 9223372036854775808 0x1_0000_0000_0000_0000";
+        let source = Source::from_string(tstr.to_string());
+        let tokens = tokenize(&source).unwrap();
+        let values: Vec<_> = tokens.iter()
+            .map(|t| (t.lexeme, t.pos.length, t.literal.clone()))
+            .collect();
+        let number = |lexeme: &'static str, value| (lexeme, lexeme.len(), Some(value));
+        assert_eq!(values, vec![
+            number("1e9", LiteralValue::LNumber(1e9)),
+            number("2.5E-3", LiteralValue::LNumber(2.5e-3)),
+            number("1_000_000", LiteralValue::LInt(1_000_000)),
+            number("0xff", LiteralValue::LInt(255)),
+            number("0o17", LiteralValue::LInt(15)),
+            number("0b1010", LiteralValue::LInt(10)),
+            number("0XA_B", LiteralValue::LInt(171)),
+            number("1", LiteralValue::LInt(1)),
+            (".", 1, None),
+            ("foo", 3, None),
+            number("3", LiteralValue::LInt(3)),
+            (".", 1, None),
+            number("9223372036854775808", LiteralValue::LBigInt("9223372036854775808".parse().unwrap())),
+            number("0x1_0000_0000_0000_0000", LiteralValue::LBigInt("18446744073709551616".parse().unwrap())),
+        ]);
+    }
+
+    #[test]
+    fn test_invalid_numbers() {
+        let error = |tstr: &str| {
+            let source = Source::from_string(tstr.to_string());
+            let e = tokenize(&source).unwrap_err();
+            (e.get_position().unwrap().linepos, e.get_message().to_string())
+        };
+        assert_eq!(error("x = 0b102;"), (9, "invalid character '2' in binary literal".to_string()));
+        assert_eq!(error("0xfg"), (4, "invalid character 'g' in hexadecimal literal".to_string()));
+        assert_eq!(error("0x;"), (3, "expected digits in hexadecimal literal".to_string()));
+        assert_eq!(error("1e+;"), (4, "expected digits in exponent".to_string()));
+        assert_eq!(error("12abc"), (3, "invalid character 'a' in number literal".to_string()));
+        assert_eq!(error("1__0"), (2, "'_' in a number literal must be between digits".to_string()));
+        assert_eq!(error("10_ "), (3, "'_' in a number literal must be between digits".to_string()));
+    }
+
     #[test]
     fn test_strings() {
         let tstr = "\"hello\" \"wor\nld\"";
@@ -554,19 +1008,129 @@ mod tests {
                     Str,
                     FilePosition::nwl(1, 1, 1),
                     "\"hello\"",
-                    LiteralValue::LString("hello"),
+                    LiteralValue::LString(Cow::Borrowed("hello")),
                 ),
                 Token::new_literal(
                     Str,
                     FilePosition::nwl(1, 9, 1),
                     "\"wor\nld\"",
-                    LiteralValue::LString("wor\nld"),
+                    LiteralValue::LString(Cow::Borrowed("wor\nld")),
                 ),
                 //Token::new(Eof, FilePosition::nwl(1, 16, 0)),
             ],
         );
     }
 
+    #[test]
+    fn test_string_escapes() {
+        let tstr = r#""a\tb\n\"q\" \\ \0 \u{41}\u{1F600}" "\r""#;
+        let source = Source::from_string(tstr.to_string());
+        let tokens = tokenize(&source).unwrap();
+        let values: Vec<_> = tokens.iter().map(|t| t.literal.clone().unwrap()).collect();
+        assert_eq!(values, vec![
+            LiteralValue::LString(Cow::Owned("a\tb\n\"q\" \\ \0 A\u{1F600}".to_string())),
+            LiteralValue::LString(Cow::Owned("\r".to_string())),
+        ]);
+    }
+
+    #[test]
+    fn test_invalid_escapes() {
+        let error = |tstr: &str| {
+            let source = Source::from_string(tstr.to_string());
+            let e = tokenize(&source).unwrap_err();
+            (e.get_position().unwrap(), e.get_message().to_string())
+        };
+        assert_eq!(
+            error("print \"ab\\q\";"),
+            (FilePosition::nwl(1, 10, 2), "invalid escape sequence: \\q".to_string()),
+        );
+        assert_eq!(
+            error("\"\n\\u{110000}\""),
+            (FilePosition::nwl(2, 1, 10), "invalid unicode code point U+110000".to_string()),
+        );
+        assert_eq!(
+            error("\"\\u{12x}\"").1,
+            "unicode escape must be \\u{...} with 1 to 6 hex digits",
+        );
+        assert_eq!(error("\"\\u41\"").1, "expected '{' after \\u in unicode escape");
+        assert!(tokenize(&Source::from_string("\"abc\\".to_string())).unwrap_err().at_eof());
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let tstr = r#""a ${x + "{b}"} ${ {} }\${c}""#;
+        let source = Source::from_string(tstr.to_string());
+        let tokens = tokenize(&source).unwrap();
+        let types: Vec<_> = tokens.iter().map(|t| (t.typ, t.lexeme)).collect();
+        assert_eq!(types, vec![
+            (Interpolation, "\"a ${"),
+            (Identifier, "x"),
+            (Plus, "+"),
+            (Str, "\"{b}\""),
+            (Interpolation, "} ${"),
+            (LeftBrace, "{"),
+            (RightBrace, "}"),
+            (Str, "}\\${c}\""),
+        ]);
+        assert_eq!(tokens[0].literal, Some(LiteralValue::LString(Cow::Borrowed("a "))));
+        assert_eq!(tokens[4].literal, Some(LiteralValue::LString(Cow::Borrowed(" "))));
+        assert_eq!(tokens[7].literal, Some(LiteralValue::LString(Cow::Owned("${c}".to_string()))));
+
+        let unclosed = tokenize(&Source::from_string("\"${x".to_string())).unwrap_err();
+        assert_eq!(unclosed.get_message(), "unterminated string interpolation");
+        assert!(unclosed.at_eof());
+    }
+
+    #[test]
+    fn test_raw_and_multiline_strings() {
+        let tstr = "r\"C:\\dir\\${x}\" \"\"\"\n    select *\n      from \"t\"\n    \"\"\" \"\"\"one\"\"\"\nx \"\"";
+        let source = Source::from_string(tstr.to_string());
+        let tokens = tokenize(&source).unwrap();
+        let values: Vec<_> = tokens.iter()
+            .map(|t| (t.pos.lineno, t.pos.linepos, t.literal.clone()))
+            .collect();
+        assert_eq!(values, vec![
+            (1, 1, Some(LiteralValue::LString(Cow::Borrowed("C:\\dir\\${x}")))),
+            (1, 16, Some(LiteralValue::LString(Cow::Owned("select *\n  from \"t\"".to_string())))),
+            (4, 9, Some(LiteralValue::LString(Cow::Owned("one".to_string())))),
+            (5, 1, None),
+            (5, 3, Some(LiteralValue::LString(Cow::Borrowed("")))),
+        ]);
+    }
+
+    #[test]
+    fn test_dedent() {
+        assert_eq!(dedent("\n  a\n\n    b\n  "), "a\n\n  b");
+        assert_eq!(dedent("\n\ta\n\tb\n"), "a\nb");
+        assert_eq!(dedent("  kept as is\n  "), "  kept as is\n  ");
+    }
+
+    #[test]
+    fn test_comments() {
+        let tstr = "a // line\n/* block /* nested */\n still */ b /**/ /// doc  \n//// not doc\nc";
+        let source = Source::from_string(tstr.to_string());
+        let tokens = tokenize(&source).unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new(Identifier, FilePosition::nwl(1, 1, 1), "a"),
+                Token::new(Identifier, FilePosition::nwl(3, 11, 1), "b"),
+                Token::new_literal(
+                    DocComment,
+                    FilePosition::nwl(3, 18, 9),
+                    "/// doc  ",
+                    LiteralValue::LString(Cow::Borrowed("doc")),
+                ),
+                Token::new(Identifier, FilePosition::nwl(5, 1, 1), "c"),
+            ],
+        );
+
+        let unclosed = tokenize(&Source::from_string("x;\n  /* a /* b */\n".to_string())).unwrap_err();
+        assert_eq!(unclosed.get_message(), "unterminated block comment");
+        assert_eq!(unclosed.get_position(), Some(FilePosition::nwl(2, 3, 2)));
+        assert!(unclosed.at_eof());
+    }
+
     #[test]
     fn test_mix() {
         let tstr = "{}( ),.-+; \n*/!!=>>=<<====else death 11.12 ";
@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::rc::Rc;
+
+use serde_json::{json, Value};
+
+use crate::ast::{Stmt, StmtKind, AST};
+use crate::environment::Environment;
+use crate::hooks::Hook;
+use crate::source::Source;
+use crate::value::{LoxType, LoxValue};
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TraceFormat {
+    Text,
+    JsonLines,
+}
+
+
+struct Record {
+    file: usize,
+    line: usize,
+    kind: &'static str,
+    reads: Vec<(String, String)>,
+    writes: Vec<(String, String)>,
+    emitted: bool,
+}
+
+
+fn stmt_kind(stmt: &Stmt) -> Option<&'static str> {
+    use StmtKind::*;
+    Some(match &stmt.kind {
+        SPrint(_) => "print",
+        SVar(_, _) => "var",
+        SExpr(_) => "expr",
+        SFun(_, _, _) => "fun",
+        SReturn(_) => "return",
+        SIf(_, _, _) => "if",
+        SWhile(_, _) => "while",
+        SBlock(_) | SEmpty => return None,
+    })
+}
+
+
+fn repr(value: &LoxValue) -> String {
+    match &**value {
+        LoxType::VStr(s) => format!("{:?}", s),
+        LoxType::VCallable(name, ..) => format!("<fun {}>", name),
+        LoxType::VNative(native) => format!("<native {}>", native.name),
+        _ => value.value_string(),
+    }
+}
+
+
+/// Logs executed statements with the variables they read and write, and
+/// function calls with their arguments and return values.
+///
+/// A statement's record is written when the next statement or call
+/// starts, so reads made after its nested statements finish (like a
+/// `while` condition being re-checked) produce a further record for the
+/// same line.
+pub struct Tracer<W: Write> {
+    out: W,
+    format: TraceFormat,
+    files: HashMap<usize, String>,
+    open: Vec<Option<Record>>,
+    depth: usize,
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(out: W, format: TraceFormat) -> Tracer<W> {
+        Tracer {
+            out,
+            format,
+            files: HashMap::new(),
+            open: Vec::new(),
+            depth: 0,
+        }
+    }
+
+    fn emit(&mut self, text: String, json: Value) {
+        let _ = match self.format {
+            TraceFormat::Text => writeln!(self.out, "{}{}", "  ".repeat(self.depth), text),
+            TraceFormat::JsonLines => writeln!(self.out, "{}", json),
+        };
+    }
+
+    fn flush(&mut self) {
+        let Some(Some(record)) = self.open.last_mut() else { return };
+        if record.emitted && record.reads.is_empty() && record.writes.is_empty() {
+            return;
+        }
+        record.emitted = true;
+        let line = record.line;
+        let kind = record.kind;
+        let file = record.file;
+        let reads = std::mem::take(&mut record.reads);
+        let writes = std::mem::take(&mut record.writes);
+
+        let filename = self.files.get(&file).cloned().unwrap_or_else(|| "__str__".to_string());
+        let mut text = format!("{}:{}: {}", filename, line, kind);
+        for (label, vars) in [("read", &reads), ("write", &writes)] {
+            if !vars.is_empty() {
+                let vars: Vec<String> = vars.iter()
+                    .map(|(name, value)| format!("{} = {}", name, value))
+                    .collect();
+                text.push_str(&format!(" | {} {}", label, vars.join(", ")));
+            }
+        }
+
+        let as_json = |vars: &Vec<(String, String)>| -> Vec<Value> {
+            vars.iter().map(|(name, value)| json!({ "name": name, "value": value })).collect()
+        };
+        let json = json!({
+            "event": "stmt",
+            "file": filename,
+            "line": line,
+            "kind": kind,
+            "depth": self.depth,
+            "reads": as_json(&reads),
+            "writes": as_json(&writes),
+        });
+        self.emit(text, json);
+    }
+
+    fn record(&mut self) -> Option<&mut Record> {
+        match self.open.last_mut() {
+            Some(Some(record)) => Some(record),
+            _ => None,
+        }
+    }
+}
+
+impl<W: Write> Drop for Tracer<W> {
+    fn drop(&mut self) {
+        while !self.open.is_empty() {
+            self.flush();
+            self.open.pop();
+        }
+        let _ = self.out.flush();
+    }
+}
+
+impl<W: Write> Hook for Tracer<W> {
+    fn loaded(&mut self, src: &Source, _ast: &AST) {
+        self.files.insert(src.id, src.filename.clone());
+    }
+
+    fn before_stmt(&mut self, stmt: &Stmt, _env: &Rc<Environment>) -> Result<(), String> {
+        self.flush();
+        self.open.push(stmt_kind(stmt).map(|kind| Record {
+            file: stmt.pos.source,
+            line: stmt.line(),
+            kind,
+            reads: Vec::new(),
+            writes: Vec::new(),
+            emitted: false,
+        }));
+        Ok(())
+    }
+
+    fn after_stmt(&mut self, _stmt: &Stmt) {
+        self.flush();
+        self.open.pop();
+    }
+
+    fn enter_call(&mut self, func: &LoxValue, args: &[LoxValue]) -> Result<(), String> {
+        self.flush();
+        let name = func.value_string();
+        let args: Vec<String> = args.iter().map(repr).collect();
+        self.emit(
+            format!("call {}({})", name, args.join(", ")),
+            json!({ "event": "call", "function": name, "args": args, "depth": self.depth }),
+        );
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn exit_call(&mut self, func: &LoxValue, result: Option<&LoxValue>) {
+        self.flush();
+        self.depth = self.depth.saturating_sub(1);
+        let name = func.value_string();
+        let value = result.map(repr);
+        self.emit(
+            match &value {
+                Some(v) => format!("return {} -> {}", name, v),
+                None => format!("return {} with error", name),
+            },
+            json!({ "event": "return", "function": name, "value": value, "depth": self.depth }),
+        );
+    }
+
+    fn read(&mut self, name: &str, value: &LoxValue) {
+        if let Some(record) = self.record() {
+            record.reads.push((name.to_string(), repr(value)));
+        }
+    }
+
+    fn write(&mut self, name: &str, value: Option<&LoxValue>) {
+        let value = value.map(repr).unwrap_or_else(|| "<uninitialized>".to_string());
+        if let Some(record) = self.record() {
+            record.writes.push((name.to_string(), value));
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hooks;
+    use crate::interpreter::Interpreter;
+    use pretty_assertions::assert_eq;
+    use std::cell::RefCell;
+
+    #[derive(Clone)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn trace(code: &str, format: TraceFormat) -> String {
+        let out = Shared(Rc::new(RefCell::new(Vec::new())));
+        hooks::install(Box::new(Tracer::new(out.clone(), format)));
+        Interpreter::new().interpret(&mut Source::from_string(code.to_string())).unwrap();
+        hooks::clear();
+        let text = String::from_utf8(out.0.borrow().clone()).unwrap();
+        text
+    }
+
+    const CODE: &str = "fun add(a, b) {
+    return a + b;
+}
+var i = 0;
+while i < 2 {
+    i = add(i, 1);
+}
+";
+
+    #[test]
+    fn test_text() {
+        assert_eq!(trace(CODE, TraceFormat::Text), "\
+__str__:1: fun | write add = <fun add>
+__str__:4: var | write i = 0
+__str__:5: while | read i = 0
+__str__:6: expr | read add = <fun add>, i = 0
+call add(0, 1)
+  __str__:2: return | read a = 0, b = 1
+return add -> 1
+__str__:6: expr | write i = 1
+__str__:5: while | read i = 1
+__str__:6: expr | read add = <fun add>, i = 1
+call add(1, 1)
+  __str__:2: return | read a = 1, b = 1
+return add -> 2
+__str__:6: expr | write i = 2
+__str__:5: while | read i = 2
+");
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let trace = trace("var s = \"x\";\nprint s;\n", TraceFormat::JsonLines);
+        let lines: Vec<Value> = trace.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines, vec![
+            json!({
+                "event": "stmt", "file": "__str__", "line": 1, "kind": "var", "depth": 0,
+                "reads": [], "writes": [{ "name": "s", "value": "\"x\"" }],
+            }),
+            json!({
+                "event": "stmt", "file": "__str__", "line": 2, "kind": "print", "depth": 0,
+                "reads": [{ "name": "s", "value": "\"x\"" }], "writes": [],
+            }),
+        ]);
+    }
+}
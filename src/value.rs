@@ -2,19 +2,71 @@ use std::fmt;
 use std::ops::Deref;
 use std::rc::Rc;
 
-use crate::ast::Stmt;
+use crate::ast::{Param, Stmt};
+use crate::bigint::BigInt;
 use crate::environment::Environment;
+use crate::source::FilePosition;
 
 
-pub type Argument = String;
+pub type Argument = Param;
+
+
+pub type NativeFn = dyn Fn(&[LoxValue]) -> Result<LoxValue, String>;
+
+
+/// A function implemented in Rust, like the ones `Interpreter` defines for
+/// script arguments.
+#[derive(Clone)]
+pub struct Native {
+    pub name: String,
+    pub arity: usize,
+    func: Rc<NativeFn>,
+}
+
+impl Native {
+    pub fn new<F>(name: &str, arity: usize, func: F) -> Native
+    where
+        F: Fn(&[LoxValue]) -> Result<LoxValue, String> + 'static,
+    {
+        Native {
+            name: name.to_string(),
+            arity,
+            func: Rc::new(func),
+        }
+    }
+
+    pub fn call(&self, args: &[LoxValue]) -> Result<LoxValue, String> {
+        (self.func)(args)
+    }
+}
+
+impl fmt::Debug for Native {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<native {}>", self.name)
+    }
+}
+
+impl PartialEq for Native {
+    fn eq(&self, other: &Native) -> bool {
+        Rc::ptr_eq(&self.func, &other.func)
+    }
+}
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum LoxType {
+    VInt(i64),
+    /// An int outside the range of `VInt`, which int arithmetic moves to
+    /// on overflow and back from when the result fits again.
+    VBigInt(BigInt),
     VNumb(f64),
     VStr(String),
     VBool(bool),
     VNil,
-    VCallable(String, Vec<Argument>, Stmt, Rc<Environment>),
+    VList(Vec<LoxValue>),
+    /// A Lox function: its name, parameters, body, the environment it
+    /// closes over and the position of its definition.
+    VCallable(String, Vec<Argument>, Rc<Stmt>, Rc<Environment>, FilePosition),
+    VNative(Native),
 }
 
 use LoxType::*;
@@ -23,16 +75,62 @@ use LoxType::*;
 impl fmt::Display for LoxType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", match self {
-            VNumb(_) => "Number",
+            VInt(_) | VBigInt(_) => "Int",
+            VNumb(_) => "Float",
             VStr(_) => "String",
             VBool(_) => "Bool",
             VNil => "Nil",
-            VCallable(_,_,_,_) => "Callable",
+            VList(_) => "List",
+            VCallable(..) | VNative(_) => "Callable",
         })
     }
 }
 
 
+// Two operands of an arithmetic operation or comparison: ints stay ints
+// unless the other is a float.
+enum Numbers {
+    Ints(i64, i64),
+    Bigs(BigInt, BigInt),
+    Floats(f64, f64),
+}
+
+fn numbers(a: &LoxType, b: &LoxType) -> Option<Numbers> {
+    let float = |v: &LoxType| match v {
+        VInt(v) => Some(*v as f64),
+        VBigInt(v) => Some(v.to_f64()),
+        VNumb(v) => Some(*v),
+        _ => None,
+    };
+    let big = |v: &LoxType| match v {
+        VInt(v) => Some(BigInt::from(*v)),
+        VBigInt(v) => Some(v.clone()),
+        _ => None,
+    };
+    Some(match (a, b) {
+        (VInt(a), VInt(b)) => Numbers::Ints(*a, *b),
+        (VNumb(_), _) | (_, VNumb(_)) => Numbers::Floats(float(a)?, float(b)?),
+        _ => Numbers::Bigs(big(a)?, big(b)?),
+    })
+}
+
+// A big int result, as a `VInt` if it fits.
+fn big(value: BigInt) -> LoxValue {
+    LoxValue::new(match value.to_i64() {
+        Some(v) => VInt(v),
+        None => VBigInt(value),
+    })
+}
+
+// The result of an int operation, redone on big ints if it overflowed.
+fn int_or_big(checked: Option<i64>, x: i64, y: i64, op: fn(&BigInt, &BigInt) -> BigInt) -> LoxValue {
+    match checked {
+        Some(v) => LoxValue::new(VInt(v)),
+        None => big(op(&BigInt::from(x), &BigInt::from(y))),
+    }
+}
+
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct LoxValue(Rc<LoxType>);
 
@@ -51,11 +149,20 @@ impl LoxValue {
 
     pub fn value_string(&self) -> String {
         match &**self {
+            VInt(v) => format!("{}", v),
+            VBigInt(v) => format!("{}", v),
+            // integral floats keep a fraction so they don't read as ints
+            VNumb(v) if v.is_finite() && v.fract() == 0.0 => format!("{:.1}", v),
             VNumb(v) => format!("{}", v),
             VStr(v) => format!("{}", v),
             VBool(v) => format!("{}", v),
             VNil => "nil".to_string(),
-            VCallable(name, _, _, _) => format!("{}", name),
+            VList(items) => format!(
+                "[{}]",
+                items.iter().map(|v| v.value_string()).collect::<Vec<_>>().join(", "),
+            ),
+            VCallable(name, ..) => name.clone(),
+            VNative(native) => native.name.clone(),
         }
     }
 
@@ -82,113 +189,174 @@ impl LoxValue {
 
     pub fn negate(&self) -> Result<LoxValue, String> {
         match &**self {
+            VInt(v) => Ok(match v.checked_neg() {
+                Some(v) => LoxValue::new(VInt(v)),
+                None => big(-&BigInt::from(*v)),
+            }),
+            VBigInt(v) => Ok(big(-v)),
             VNumb(v) => Ok(LoxValue::new(VNumb(-v))),
             typ => Err(format!("Cannot negate {}", typ)),
         }
     }
 
     pub fn sub(&self, b: &LoxValue) -> Result<LoxValue, String> {
-        match (&**self, &**b) {
-            (VNumb(a), VNumb(b)) => Ok(LoxValue::new(VNumb(a - b))),
-            (a, b) => Err(format!("Cannot subtract {} from {}", a, b)),
+        match numbers(self, b) {
+            Some(Numbers::Ints(x, y)) => Ok(int_or_big(x.checked_sub(y), x, y, |x, y| x - y)),
+            Some(Numbers::Bigs(x, y)) => Ok(big(&x - &y)),
+            Some(Numbers::Floats(x, y)) => Ok(LoxValue::new(VNumb(x - y))),
+            None => Err(format!("Cannot subtract {} from {}", **self, **b)),
         }
     }
 
     pub fn add(&self, b: &LoxValue) -> Result<LoxValue, String> {
-        match (&**self, &**b) {
-            (VNumb(a), VNumb(b)) => Ok(LoxValue::new(VNumb(a + b))),
-            (VStr(a), VStr(b)) => Ok(LoxValue::new(VStr(a.to_string() + &b))),
-            (a, b) => Err(format!("Cannot add {} to {}", a, b)),
+        match (numbers(self, b), &**self, &**b) {
+            (Some(Numbers::Ints(x, y)), _, _) => Ok(int_or_big(x.checked_add(y), x, y, |x, y| x + y)),
+            (Some(Numbers::Bigs(x, y)), _, _) => Ok(big(&x + &y)),
+            (Some(Numbers::Floats(x, y)), _, _) => Ok(LoxValue::new(VNumb(x + y))),
+            (None, VStr(a), VStr(b)) => Ok(LoxValue::new(VStr(a.to_string() + b))),
+            (None, a, b) => Err(format!("Cannot add {} to {}", a, b)),
         }
     }
 
     pub fn mul(&self, b: &LoxValue) -> Result<LoxValue, String> {
-        match (&**self, &**b) {
-            (VNumb(a), VNumb(b)) => Ok(LoxValue::new(VNumb(a * b))),
-            (VStr(a), VNumb(b)) => Ok(LoxValue::new(VStr(a.repeat(*b as usize)))),
-            (VNumb(a), VStr(b)) => Ok(LoxValue::new(VStr(b.repeat(*a as usize)))),
-            (a, b) => Err(format!("Cannot multiply {} by {}", a, b)),
+        match (numbers(self, b), &**self, &**b) {
+            (Some(Numbers::Ints(x, y)), _, _) => Ok(int_or_big(x.checked_mul(y), x, y, |x, y| x * y)),
+            (Some(Numbers::Bigs(x, y)), _, _) => Ok(big(&x * &y)),
+            (Some(Numbers::Floats(x, y)), _, _) => Ok(LoxValue::new(VNumb(x * y))),
+            (None, VStr(s), VInt(n)) | (None, VInt(n), VStr(s)) => {
+                // `repeat` panics on lengths no String can have
+                let count = usize::try_from(*n).ok()
+                    .filter(|&count| s.len().checked_mul(count).is_some_and(|len| len <= isize::MAX as usize));
+                match count {
+                    Some(count) => Ok(LoxValue::new(VStr(s.repeat(count)))),
+                    None => Err(format!("Cannot repeat a String {} times", n)),
+                }
+            },
+            (None, a, b) => Err(format!("Cannot multiply {} by {}", a, b)),
         }
     }
 
+    /// Division, which always gives a float.
     pub fn div(&self, b: &LoxValue) -> Result<LoxValue, String> {
-        match (&**self, &**b) {
-            (VNumb(a), VNumb(b)) => Ok(LoxValue::new(VNumb(a / b))),
-            (a, b) => Err(format!("Cannot divide {} by {}", a, b)),
+        match numbers(self, b) {
+            Some(Numbers::Ints(x, y)) => Ok(LoxValue::new(VNumb(x as f64 / y as f64))),
+            // dividing exactly first keeps precision when the operands
+            // are too big for floats
+            Some(Numbers::Bigs(x, y)) => Ok(LoxValue::new(VNumb(match x.div_rem(&y) {
+                Some((quotient, rem)) => quotient.to_f64() + rem.to_f64() / y.to_f64(),
+                None => x.to_f64() / 0.0,
+            }))),
+            Some(Numbers::Floats(x, y)) => Ok(LoxValue::new(VNumb(x / y))),
+            None => Err(format!("Cannot divide {} by {}", **self, **b)),
+        }
+    }
+
+    /// The remainder of division, with the sign of `self`.
+    pub fn rem(&self, b: &LoxValue) -> Result<LoxValue, String> {
+        let by_zero = || format!("Cannot take the remainder of {} divided by 0", self.value_string());
+        match numbers(self, b) {
+            Some(Numbers::Ints(_, 0)) => Err(by_zero()),
+            // only i64::MIN % -1 overflows, and it is 0
+            Some(Numbers::Ints(x, y)) => Ok(LoxValue::new(VInt(x.checked_rem(y).unwrap_or(0)))),
+            Some(Numbers::Bigs(x, y)) => x.div_rem(&y).map(|(_, rem)| big(rem)).ok_or_else(by_zero),
+            Some(Numbers::Floats(x, y)) => Ok(LoxValue::new(VNumb(x % y))),
+            None => Err(format!("Cannot take the remainder of {} divided by {}", **self, **b)),
+        }
+    }
+
+    /// The value as an int, truncating floats and parsing strings, for
+    /// the `int()` builtin.
+    pub fn to_int(&self) -> Result<LoxValue, String> {
+        let value = match &**self {
+            VInt(_) | VBigInt(_) => return Ok(self.clone()),
+            VNumb(v) => BigInt::from_f64(*v),
+            VStr(s) => s.trim().parse().ok(),
+            _ => None,
+        };
+        match value {
+            Some(v) => Ok(big(v)),
+            None => Err(format!("Cannot convert {} {} to Int", **self, self.value_string())),
+        }
+    }
+
+    /// The value as a float, parsing strings, for the `float()` builtin.
+    pub fn to_float(&self) -> Result<LoxValue, String> {
+        let value = match &**self {
+            VInt(v) => Some(*v as f64),
+            VBigInt(v) => Some(v.to_f64()),
+            VNumb(v) => Some(*v),
+            VStr(s) => s.trim().parse().ok(),
+            _ => None,
+        };
+        match value {
+            Some(v) => Ok(LoxValue::new(VNumb(v))),
+            None => Err(format!("Cannot convert {} {} to Float", **self, self.value_string())),
         }
     }
 
     pub fn neq(&self, b: &LoxValue) -> Result<LoxValue, String> {
-        match (&**self, &**b) {
-            (VNumb(a), VNumb(b)) => Ok(LoxValue::new(VBool(a != b))),
-            (VStr(a), VStr(b)) => Ok(LoxValue::new(VBool(a != b))),
-            (VBool(a), VBool(b)) => Ok(LoxValue::new(VBool(a != b))),
+        match (numbers(self, b), &**self, &**b) {
+            (Some(Numbers::Ints(a, b)), _, _) => Ok(LoxValue::new(VBool(a != b))),
+            (Some(Numbers::Bigs(a, b)), _, _) => Ok(LoxValue::new(VBool(a != b))),
+            (Some(Numbers::Floats(a, b)), _, _) => Ok(LoxValue::new(VBool(a != b))),
+            (_, VStr(a), VStr(b)) => Ok(LoxValue::new(VBool(a != b))),
+            (_, VBool(a), VBool(b)) => Ok(LoxValue::new(VBool(a != b))),
             _ => Ok(LoxValue::new(VBool(true))),
         }
     }
 
     pub fn eq(&self, b: &LoxValue) -> Result<LoxValue, String> {
-        match (&**self, &**b) {
-            (VNumb(a), VNumb(b)) => Ok(LoxValue::new(VBool(a == b))),
-            (VStr(a), VStr(b)) => Ok(LoxValue::new(VBool(a == b))),
-            (VBool(a), VBool(b)) => Ok(LoxValue::new(VBool(a == b))),
+        match (numbers(self, b), &**self, &**b) {
+            (Some(Numbers::Ints(a, b)), _, _) => Ok(LoxValue::new(VBool(a == b))),
+            (Some(Numbers::Bigs(a, b)), _, _) => Ok(LoxValue::new(VBool(a == b))),
+            (Some(Numbers::Floats(a, b)), _, _) => Ok(LoxValue::new(VBool(a == b))),
+            (_, VStr(a), VStr(b)) => Ok(LoxValue::new(VBool(a == b))),
+            (_, VBool(a), VBool(b)) => Ok(LoxValue::new(VBool(a == b))),
             _ => Ok(LoxValue::new(VBool(false))),
         }
     }
 
     pub fn gt(&self, b: &LoxValue) -> Result<LoxValue, String> {
-        match (&**self, &**b) {
-            (VNumb(a), VNumb(b)) => Ok(LoxValue::new(VBool(a > b))),
-            (VStr(a), VStr(b)) => Ok(LoxValue::new(VBool(a > b))),
-            (VBool(a), VBool(b)) => Ok(LoxValue::new(VBool(a > b))),
+        match (numbers(self, b), &**self, &**b) {
+            (Some(Numbers::Ints(a, b)), _, _) => Ok(LoxValue::new(VBool(a > b))),
+            (Some(Numbers::Bigs(a, b)), _, _) => Ok(LoxValue::new(VBool(a > b))),
+            (Some(Numbers::Floats(a, b)), _, _) => Ok(LoxValue::new(VBool(a > b))),
+            (_, VStr(a), VStr(b)) => Ok(LoxValue::new(VBool(a > b))),
+            (_, VBool(a), VBool(b)) => Ok(LoxValue::new(VBool(a > b))),
             _ => Ok(LoxValue::new(VBool(false))),
         }
     }
 
     pub fn ge(&self, b: &LoxValue) -> Result<LoxValue, String> {
-        match (&**self, &**b) {
-            (VNumb(a), VNumb(b)) => Ok(LoxValue::new(VBool(a >= b))),
-            (VStr(a), VStr(b)) => Ok(LoxValue::new(VBool(a >= b))),
-            (VBool(a), VBool(b)) => Ok(LoxValue::new(VBool(a >= b))),
+        match (numbers(self, b), &**self, &**b) {
+            (Some(Numbers::Ints(a, b)), _, _) => Ok(LoxValue::new(VBool(a >= b))),
+            (Some(Numbers::Bigs(a, b)), _, _) => Ok(LoxValue::new(VBool(a >= b))),
+            (Some(Numbers::Floats(a, b)), _, _) => Ok(LoxValue::new(VBool(a >= b))),
+            (_, VStr(a), VStr(b)) => Ok(LoxValue::new(VBool(a >= b))),
+            (_, VBool(a), VBool(b)) => Ok(LoxValue::new(VBool(a >= b))),
             _ => Ok(LoxValue::new(VBool(false))),
         }
     }
 
     pub fn lt(&self, b: &LoxValue) -> Result<LoxValue, String> {
-        match (&**self, &**b) {
-            (VNumb(a), VNumb(b)) => Ok(LoxValue::new(VBool(a < b))),
-            (VStr(a), VStr(b)) => Ok(LoxValue::new(VBool(a < b))),
-            (VBool(a), VBool(b)) => Ok(LoxValue::new(VBool(a < b))),
+        match (numbers(self, b), &**self, &**b) {
+            (Some(Numbers::Ints(a, b)), _, _) => Ok(LoxValue::new(VBool(a < b))),
+            (Some(Numbers::Bigs(a, b)), _, _) => Ok(LoxValue::new(VBool(a < b))),
+            (Some(Numbers::Floats(a, b)), _, _) => Ok(LoxValue::new(VBool(a < b))),
+            (_, VStr(a), VStr(b)) => Ok(LoxValue::new(VBool(a < b))),
+            (_, VBool(a), VBool(b)) => Ok(LoxValue::new(VBool(a < b))),
             _ => Ok(LoxValue::new(VBool(false))),
         }
     }
 
     pub fn le(&self, b: &LoxValue) -> Result<LoxValue, String> {
-        match (&**self, &**b) {
-            (VNumb(a), VNumb(b)) => Ok(LoxValue::new(VBool(a <= b))),
-            (VStr(a), VStr(b)) => Ok(LoxValue::new(VBool(a <= b))),
-            (VBool(a), VBool(b)) => Ok(LoxValue::new(VBool(a <= b))),
+        match (numbers(self, b), &**self, &**b) {
+            (Some(Numbers::Ints(a, b)), _, _) => Ok(LoxValue::new(VBool(a <= b))),
+            (Some(Numbers::Bigs(a, b)), _, _) => Ok(LoxValue::new(VBool(a <= b))),
+            (Some(Numbers::Floats(a, b)), _, _) => Ok(LoxValue::new(VBool(a <= b))),
+            (_, VStr(a), VStr(b)) => Ok(LoxValue::new(VBool(a <= b))),
+            (_, VBool(a), VBool(b)) => Ok(LoxValue::new(VBool(a <= b))),
             _ => Ok(LoxValue::new(VBool(false))),
         }
     }
-
-    pub fn and(&self, b: &LoxValue) -> Result<LoxValue, String> {
-        Ok(LoxValue::new(VBool(match &**self {
-            VBool(v) => *v,
-            _ => self._is_truthy(),
-        } && match &**self {
-            VBool(v) => *v,
-            _ => b._is_truthy(),
-        })))
-    }
-
-    pub fn or(&self, b: &LoxValue) -> Result<LoxValue, String> {
-        Ok(LoxValue::new(VBool(match &**self {
-            VBool(v) => *v,
-            _ => self._is_truthy(),
-        } || match &**self {
-            VBool(v) => *v,
-            _ => b._is_truthy(),
-        })))
-    }
 }
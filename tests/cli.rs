@@ -0,0 +1,97 @@
+use std::process::{Command, Output};
+
+
+fn bwl(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_bwl"))
+        .args(args)
+        .output()
+        .expect("failed to run bwl")
+}
+
+
+fn write_script(name: &str, code: &str) -> String {
+    let path = std::env::temp_dir().join(name);
+    std::fs::write(&path, code).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+
+#[test]
+fn test_run_with_args() {
+    let script = write_script("bwl_cli_args.lox", "print argc;\nprint arg(0) + arg(1);\n");
+    let output = bwl(&["run", &script, "a", "-b"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "2\na-b\n");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+
+    let output = bwl(&["-c", "print arg(0);", "x"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "x\n");
+}
+
+
+#[test]
+fn test_exit_codes() {
+    let compile = write_script("bwl_cli_compile.lox", "print 1 +;\n");
+    let runtime = write_script("bwl_cli_runtime.lox", "print 1;\nprint -\"a\";\n");
+
+    assert_eq!(bwl(&[&compile]).status.code(), Some(65));
+    assert_eq!(bwl(&["check", &compile]).status.code(), Some(65));
+    assert_eq!(bwl(&["check", &runtime]).status.code(), Some(0));
+
+    let output = bwl(&[&runtime]);
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+
+    assert_eq!(bwl(&["no/such/file.lox"]).status.code(), Some(74));
+}
+
+
+#[test]
+fn test_tokens_and_ast() {
+    let script = write_script("bwl_cli_dump.lox", "var x = 1;\n");
+    let output = bwl(&["tokens", &script]);
+    let tokens = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(tokens.lines().next(), Some("   1:1    Var          \"var\""));
+
+    let output = bwl(&["ast", &script]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "var x = 1\n");
+}
+
+
+#[test]
+fn test_dump_ast() {
+    let script = write_script("bwl_cli_dump_ast.lox", "if 1 < 2 { print 2 * 3; }\n");
+    let output = bwl(&["run", "--dump-ast", &script]);
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "if (1 < 2)\n  block\n    print (2 * 3)\n");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "6\n");
+
+    // without --optimize, the optimized tree is the one after all passes
+    let output = bwl(&["run", "--dump-ast=optimized", &script]);
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "print 6\n");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "6\n");
+
+    let output = bwl(&["run", "--dump-ast=optimized", "--optimize=fold", &script]);
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "if true\n  block\n    print 6\n");
+}
+
+
+#[test]
+fn test_profile() {
+    let code = "fun f() { return 1; }\nprint f();\n";
+    let script = write_script("bwl_cli_profile.lox", code);
+    let folded = std::env::temp_dir().join("bwl_cli_profile.folded");
+    let _ = std::fs::remove_file(&folded);
+
+    let output = bwl(&["run", &format!("--profile={}", folded.display()), &script]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+    assert!(std::fs::read_to_string(&folded).unwrap().contains("<script>;f:1 "));
+
+    // the script after a bare --profile is run, not overwritten
+    let output = Command::new(env!("CARGO_BIN_EXE_bwl"))
+        .args(["run", "--profile", &script])
+        .current_dir(std::env::temp_dir())
+        .output()
+        .expect("failed to run bwl");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+    assert_eq!(std::fs::read_to_string(&script).unwrap(), code);
+}
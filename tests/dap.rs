@@ -0,0 +1,180 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+use serde_json::{json, Value};
+
+
+/// A minimal scripted DAP client talking to `bwl dap` over stdio.
+struct Client {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    seq: i64,
+    events: Vec<Value>,
+}
+
+impl Client {
+    fn start() -> Client {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_bwl"))
+            .arg("dap")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("failed to start bwl dap");
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Client { child, stdin, stdout, seq: 0, events: Vec::new() }
+    }
+
+    fn read(&mut self) -> Value {
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            assert!(self.stdout.read_line(&mut header).unwrap() > 0, "server closed stdout");
+            let header = header.trim();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(value) = header.strip_prefix("Content-Length:") {
+                length = value.trim().parse().unwrap();
+            }
+        }
+        let mut body = vec![0; length];
+        self.stdout.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn request(&mut self, command: &str, arguments: Value) -> Value {
+        self.seq += 1;
+        let body = json!({
+            "seq": self.seq,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        }).to_string();
+        write!(self.stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        self.stdin.flush().unwrap();
+
+        loop {
+            let msg = self.read();
+            if msg["type"] == "response" && msg["request_seq"] == self.seq {
+                assert_eq!(msg["command"], command);
+                return msg;
+            }
+            self.events.push(msg);
+        }
+    }
+
+    fn wait_for(&mut self, event: &str) -> Value {
+        if let Some(idx) = self.events.iter().position(|e| e["event"] == event) {
+            return self.events.remove(idx);
+        }
+        loop {
+            let msg = self.read();
+            if msg["event"] == event {
+                return msg;
+            }
+            self.events.push(msg);
+        }
+    }
+
+    fn output(&mut self) -> Vec<String> {
+        let mut output = Vec::new();
+        self.events.retain(|e| {
+            if e["event"] == "output" && e["body"]["category"] == "stdout" {
+                output.push(e["body"]["output"].as_str().unwrap().trim().to_string());
+                false
+            } else {
+                true
+            }
+        });
+        output
+    }
+}
+
+
+#[test]
+fn test_debug_fact() {
+    let program = concat!(env!("CARGO_MANIFEST_DIR"), "/loxfiles/fact.lox");
+    let mut client = Client::start();
+
+    let init = client.request("initialize", json!({ "adapterID": "bwl" }));
+    assert_eq!(init["success"], true);
+    client.wait_for("initialized");
+
+    assert_eq!(client.request("launch", json!({ "program": program }))["success"], true);
+    let bps = client.request("setBreakpoints", json!({
+        "source": { "path": program },
+        "breakpoints": [{ "line": 7 }],
+    }));
+    assert_eq!(bps["body"]["breakpoints"][0]["verified"], true);
+    client.request("configurationDone", json!({}));
+
+    // fact(1) returns on line 7 straight away
+    let stopped = client.wait_for("stopped");
+    assert_eq!(stopped["body"]["reason"], "breakpoint");
+
+    let threads = client.request("threads", json!({}));
+    assert_eq!(threads["body"]["threads"][0]["id"], 1);
+
+    let trace = client.request("stackTrace", json!({ "threadId": 1 }));
+    let frames = trace["body"]["stackFrames"].as_array().unwrap();
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0]["name"], "fact");
+    assert_eq!(frames[0]["line"], 7);
+    assert_eq!(frames[1]["name"], "<script>");
+    assert_eq!(frames[1]["line"], 14);
+
+    let scopes = client.request("scopes", json!({ "frameId": frames[0]["id"] }));
+    let scopes = scopes["body"]["scopes"].as_array().unwrap();
+    assert_eq!(scopes.first().unwrap()["name"], "Locals");
+    assert_eq!(scopes.last().unwrap()["name"], "Globals");
+
+    let locals = client.request("variables", json!({
+        "variablesReference": scopes[0]["variablesReference"],
+    }));
+    assert_eq!(locals["body"]["variables"], json!([
+        { "name": "n", "value": "1", "type": "Int", "variablesReference": 0 },
+    ]));
+
+    let globals = client.request("variables", json!({
+        "variablesReference": scopes.last().unwrap()["variablesReference"],
+    }));
+    let names: Vec<&str> = globals["body"]["variables"].as_array().unwrap()
+        .iter()
+        .map(|v| v["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, vec!["fact", "i"]);
+
+    let result = client.request("evaluate", json!({
+        "expression": "n + 41",
+        "frameId": frames[0]["id"],
+    }));
+    assert_eq!(result["body"]["result"], "42");
+
+    // step out of fact(1) back into the loop body
+    client.request("stepOut", json!({}));
+    assert_eq!(client.wait_for("stopped")["body"]["reason"], "step");
+    let trace = client.request("stackTrace", json!({ "threadId": 1 }));
+    assert_eq!(trace["body"]["stackFrames"][0]["name"], "<script>");
+    assert_eq!(trace["body"]["stackFrames"][0]["line"], 15);
+
+    // fact(2) recurses into fact(1) before hitting the breakpoint again
+    client.request("continue", json!({}));
+    client.wait_for("stopped");
+    let trace = client.request("stackTrace", json!({ "threadId": 1 }));
+    assert_eq!(trace["body"]["totalFrames"], 3);
+
+    client.request("setBreakpoints", json!({
+        "source": { "path": program },
+        "breakpoints": [],
+    }));
+    client.request("continue", json!({}));
+
+    assert_eq!(client.wait_for("exited")["body"]["exitCode"], 0);
+    client.wait_for("terminated");
+    assert_eq!(client.output(), vec!["1", "2", "6", "24", "120", "720", "5040", "40320", "362880"]);
+
+    client.request("disconnect", json!({}));
+    assert!(client.child.wait().unwrap().success());
+}
@@ -0,0 +1,58 @@
+use std::path::PathBuf;
+
+use bagelwithlox::compiler::Backend;
+use bagelwithlox::golden;
+use bagelwithlox::optimizer::Passes;
+use bagelwithlox::parser::Dialect;
+
+
+// Files that take most of the time, so only the tree-walker runs them by
+// default.
+const HEAVY: &[&str] = &["mandel.lox", "mandel_loop.lox"];
+
+
+fn check_loxfiles(passes: Passes, backend: Backend, keep: impl Fn(&str) -> bool) {
+    let dir = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/loxfiles"));
+    let files: Vec<PathBuf> = golden::collect(&[dir]).unwrap().into_iter()
+        .filter(|path| keep(&path.file_name().unwrap().to_string_lossy()))
+        .collect();
+    assert!(!files.is_empty());
+
+    let failures: Vec<String> = golden::run_all(&files, 4, Dialect::Bwl, passes, backend).into_iter()
+        .filter(|outcome| !outcome.passed())
+        .map(|outcome| format!("{}:\n    {}", outcome.path.display(), outcome.failures.join("\n    ")))
+        .collect();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+
+fn is_light(name: &str) -> bool {
+    !HEAVY.contains(&name)
+}
+
+
+#[test]
+fn test_loxfiles() {
+    check_loxfiles(Passes::default(), Backend::Tree, |_| true);
+}
+
+
+#[test]
+fn test_loxfiles_optimized() {
+    check_loxfiles(Passes::all(), Backend::Tree, is_light);
+}
+
+
+#[test]
+fn test_loxfiles_closures() {
+    check_loxfiles(Passes::default(), Backend::Closures, is_light);
+}
+
+
+#[test]
+#[ignore = "slow; run with --ignored"]
+fn test_heavy_loxfiles() {
+    let heavy = |name: &str| HEAVY.contains(&name);
+    check_loxfiles(Passes::all(), Backend::Tree, heavy);
+    check_loxfiles(Passes::default(), Backend::Closures, heavy);
+}
@@ -0,0 +1,33 @@
+// The only test in its own binary, so that no other test allocates while
+// it measures the memory of the process.
+
+use bagelwithlox::interpreter::Interpreter;
+use bagelwithlox::source::Source;
+
+
+// The most memory the process has had resident, in bytes, on Linux.
+fn peak_resident() -> Option<usize> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    let kib: usize = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+
+#[test]
+fn test_tail_calls_use_constant_memory() {
+    let mut interpreter = Interpreter::new();
+    let mut warm_up = |n: usize| {
+        let code = format!("fun count(n) {{ if n == 0 {{ return 0; }} return count(n - 1); }} return count({});", n);
+        interpreter.interpret(&mut Source::from_string(code)).unwrap()
+    };
+    warm_up(1000);
+
+    // keeping anything per call would take 8 MiB here
+    let before = peak_resident();
+    assert_eq!(warm_up(1000000), Some("0".to_string()));
+    if let (Some(before), Some(after)) = (before, peak_resident()) {
+        let grown = after.saturating_sub(before);
+        assert!(grown < 4 << 20, "grew by {} bytes", grown);
+    }
+}